@@ -0,0 +1,35 @@
+//! Arbitrary `#[path]` values and `include!` arguments, relative to files that
+//! exist, so that resolution goes on into directories and files they name
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use srcfiles::{ModType, Options, SourceFileDesc, SourceFileType};
+
+fuzz_target!(|value: &str| {
+    let options = Options::default();
+    let content = format!(
+        "#[path = {value:?}]\nmod a;\n\
+         #[path = {value:?}]\nmod b {{ mod c; }}\n\
+         #[cfg_attr(unix, path = {value:?})]\nmod d;\n\
+         fn f() {{ #[path = {value:?}] mod e {{ #[path = {value:?}] mod g; }} }}\n\
+         include!({value:?});\n",
+        value = value
+    );
+
+    for (path, mod_type) in [
+        ("", ModType::ModRs),
+        ("/", ModType::ModRs),
+        (
+            concat!(env!("CARGO_MANIFEST_DIR"), "/../src/lib.rs"),
+            ModType::ModRs,
+        ),
+        (
+            concat!(env!("CARGO_MANIFEST_DIR"), "/../src/cfg.rs"),
+            ModType::Adjacent,
+        ),
+    ] {
+        let root = SourceFileDesc::new(path.into(), SourceFileType::RustSource(mod_type));
+        let _ = srcfiles::process_source_text(&root, &content, &options);
+    }
+});
@@ -0,0 +1,29 @@
+//! Arbitrary source text as a crate root, a non-mod.rs module, a function body
+//! and an expression
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use srcfiles::{ModType, Options, SourceFileDesc, SourceFileType};
+
+fuzz_target!(|content: &str| {
+    let options = Options {
+        scan_unknown_macros: true,
+        ..Options::default()
+    };
+
+    let root = SourceFileDesc::new(
+        "fuzz/src/lib.rs".into(),
+        SourceFileType::RustSource(ModType::ModRs),
+    );
+    let module = SourceFileDesc::new(
+        "fuzz/src/module.rs".into(),
+        SourceFileType::RustSource(ModType::Adjacent),
+    );
+
+    let _ = srcfiles::process_source_text(&root, content, &options);
+    let _ = srcfiles::process_source_text(&module, content, &options);
+    let _ = srcfiles::process_source_text(&module, &format!("fn f() {{ {} }}", content), &options);
+    let _ =
+        srcfiles::process_source_text(&module, &format!("const _: () = ({});", content), &options);
+});
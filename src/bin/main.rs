@@ -1,22 +1,81 @@
-use ::srcfiles::crate_srcfiles;
+use ::srcfiles::{crate_srcfiles, CfgSet, Diagnostic, Options, PathStyle, SourceOrder};
 
 use std::env;
 use std::process;
 
+fn usage() -> ! {
+    eprintln!(
+        "Usage: srcfiles [--cfg SPEC]... [--env KEY=VALUE]... [--target-dir DIR [--package NAME]]"
+    );
+    eprintln!("                [--order tree|path] [--paths crate|absolute | --relative-to DIR]");
+    eprintln!("                [--scan-unknown-macros] path/to/main/or/lib.rs");
+    process::exit(1);
+}
+
 fn main() {
     let mut args = env::args();
     let _ = args.next(); // executable name
 
-    let filename = match (args.next(), args.next()) {
-        (Some(filename), None) => filename,
-        _ => {
-            eprintln!("Usage: srcfiles path/to/main/or/lib.rs");
-            process::exit(1);
+    let mut options = Options::default();
+    let mut filename = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--cfg" => match args.next() {
+                Some(spec) => options
+                    .cfg_set
+                    .get_or_insert_with(CfgSet::new)
+                    .insert_spec(&spec),
+                None => usage(),
+            },
+            "--env" => match args.next().as_ref().and_then(|x| x.split_once('=')) {
+                Some((key, value)) => {
+                    options.env.insert(key.to_owned(), value.to_owned());
+                }
+                None => usage(),
+            },
+            "--target-dir" => match args.next() {
+                Some(target_dir) => options.target_dir = Some(target_dir.into()),
+                None => usage(),
+            },
+            "--package" => match args.next() {
+                Some(package) => options.package = Some(package),
+                None => usage(),
+            },
+            "--order" => match args.next().as_deref() {
+                Some("tree") => options.order = SourceOrder::ModuleTree,
+                Some("path") => options.order = SourceOrder::Path,
+                _ => usage(),
+            },
+            "--paths" => match args.next().as_deref() {
+                Some("crate") => options.path_style = PathStyle::RelativeToCrateRoot,
+                Some("absolute") => options.path_style = PathStyle::Absolute,
+                _ => usage(),
+            },
+            "--relative-to" => match args.next() {
+                Some(base) => options.path_style = PathStyle::RelativeTo(base.into()),
+                None => usage(),
+            },
+            "--scan-unknown-macros" => options.scan_unknown_macros = true,
+            _ if filename.is_none() => filename = Some(arg),
+            _ => usage(),
         }
+    }
+
+    let filename = match filename {
+        Some(filename) => filename,
+        None => usage(),
     };
 
-    match crate_srcfiles(filename.into()) {
+    match crate_srcfiles(filename.into(), &options) {
         Ok(srcfiles) => println!("{:?}", srcfiles),
-        Err(srcfiles_with_errors) => println!("{}", srcfiles_with_errors),
+        Err(srcfiles_with_errors) => {
+            for diagnostic in Diagnostic::from_errors(&srcfiles_with_errors) {
+                eprintln!("{}", diagnostic);
+            }
+
+            println!("{:?}", srcfiles_with_errors.into_sources());
+            process::exit(1);
+        }
     };
 }
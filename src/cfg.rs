@@ -0,0 +1,276 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::path::Path;
+
+use proc_macro2::{Delimiter, TokenStream, TokenTree};
+use quote::ToTokens;
+use syn::{Attribute, Lit, Meta, NestedMeta};
+
+use crate::common::ToTokenString;
+use crate::error::Error;
+use crate::location::Location;
+
+/// Set of configuration options `#[cfg]` predicates are evaluated against,
+/// e.g. `unix`, `test` or `feature = "tls"`
+#[derive(Debug, Clone, Default)]
+pub struct CfgSet {
+    options: HashSet<(String, Option<String>)>,
+}
+
+impl CfgSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert_flag(&mut self, name: impl Into<String>) {
+        self.options.insert((name.into(), None));
+    }
+
+    pub fn insert_key_value(&mut self, key: impl Into<String>, value: impl Into<String>) {
+        self.options.insert((key.into(), Some(value.into())));
+    }
+
+    /// Inserts an option written in rustc's `--cfg` syntax: `name` or `key="value"`
+    pub fn insert_spec(&mut self, spec: &str) {
+        match spec.find('=') {
+            Some(eq) => {
+                self.insert_key_value(spec[..eq].trim(), spec[eq + 1..].trim().trim_matches('"'))
+            }
+            None => self.insert_flag(spec.trim()),
+        }
+    }
+
+    pub fn contains_flag(&self, name: &str) -> bool {
+        self.options.contains(&(name.to_owned(), None))
+    }
+
+    pub fn contains_key_value(&self, key: &str, value: &str) -> bool {
+        self.options
+            .contains(&(key.to_owned(), Some(value.to_owned())))
+    }
+}
+
+/// Parsed `#[cfg]` predicate
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Cfg {
+    Flag(String),
+    KeyValue(String, String),
+    All(Vec<Cfg>),
+    Any(Vec<Cfg>),
+    Not(Box<Cfg>),
+}
+
+impl Cfg {
+    /// Conjunction of `predicates`, `None` when there are none
+    pub fn all(predicates: impl IntoIterator<Item = Cfg>) -> Option<Self> {
+        let mut flattened = Vec::new();
+
+        for predicate in predicates {
+            match predicate {
+                Self::All(nested) => flattened.extend(nested),
+                predicate => flattened.push(predicate),
+            }
+        }
+
+        match flattened.len() {
+            0 => None,
+            1 => flattened.pop(),
+            _ => Some(Self::All(flattened)),
+        }
+    }
+
+    /// Disjunction of `predicates`, `None` when there are none
+    pub fn any(predicates: impl IntoIterator<Item = Cfg>) -> Option<Self> {
+        let mut flattened = Vec::new();
+
+        for predicate in predicates {
+            match predicate {
+                Self::Any(nested) => flattened.extend(nested),
+                predicate => flattened.push(predicate),
+            }
+        }
+
+        match flattened.len() {
+            0 => None,
+            1 => flattened.pop(),
+            _ => Some(Self::Any(flattened)),
+        }
+    }
+
+    pub fn eval(&self, cfg_set: &CfgSet) -> bool {
+        match self {
+            Self::Flag(name) => cfg_set.contains_flag(name),
+            Self::KeyValue(key, value) => cfg_set.contains_key_value(key, value),
+            Self::All(predicates) => predicates.iter().all(|x| x.eval(cfg_set)),
+            Self::Any(predicates) => predicates.iter().any(|x| x.eval(cfg_set)),
+            Self::Not(predicate) => !predicate.eval(cfg_set),
+        }
+    }
+
+    /// Parses the predicate of a `#[cfg(...)]` attribute, returns `None` for other
+    /// attributes. `file` is the file containing the attribute, for error locations
+    pub fn from_attr(attr: &Attribute, file: &Path) -> Option<Result<Self, Error>> {
+        if !attr.path.is_ident("cfg") {
+            return None;
+        }
+
+        let invalid = || {
+            Error::InvalidCfg(
+                attr.to_token_string(),
+                Location::new(file, attr.pound_token.span),
+            )
+        };
+
+        Some(match attr.parse_meta() {
+            Ok(Meta::List(list)) if list.nested.len() == 1 => list
+                .nested
+                .first()
+                .and_then(Self::from_nested_meta)
+                .ok_or_else(invalid),
+            _ => Err(invalid()),
+        })
+    }
+
+    fn from_nested_meta(nested_meta: &NestedMeta) -> Option<Self> {
+        let meta = match nested_meta {
+            NestedMeta::Meta(meta) => meta,
+            NestedMeta::Lit(_) => return None,
+        };
+
+        match meta {
+            Meta::Path(path) => Some(Self::Flag(path.get_ident()?.to_string())),
+            Meta::NameValue(name_value) => match &name_value.lit {
+                Lit::Str(value) => Some(Self::KeyValue(
+                    name_value.path.get_ident()?.to_string(),
+                    value.value(),
+                )),
+                _ => None,
+            },
+            Meta::List(list) => {
+                let mut predicates = list
+                    .nested
+                    .iter()
+                    .map(Self::from_nested_meta)
+                    .collect::<Option<Vec<_>>>()?;
+
+                match list.path.get_ident()?.to_string().as_str() {
+                    "all" => Some(Self::All(predicates)),
+                    "any" => Some(Self::Any(predicates)),
+                    "not" if predicates.len() == 1 => Some(Self::Not(Box::new(predicates.pop()?))),
+                    _ => None,
+                }
+            }
+        }
+    }
+}
+
+/// Attribute produced by expanding `cfg_attr`s, along with the predicates guarding it
+pub(crate) struct ExpandedAttr {
+    pub cfg: Vec<Cfg>,
+    /// Attribute contents without the surrounding `#[...]`, e.g. `path = "a.rs"`
+    pub tokens: TokenStream,
+}
+
+fn split_commas(tokens: TokenStream) -> Vec<TokenStream> {
+    let mut chunks = vec![TokenStream::new()];
+
+    for token in tokens {
+        match token {
+            TokenTree::Punct(ref punct) if punct.as_char() == ',' => {
+                chunks.push(TokenStream::new())
+            }
+            token => {
+                if let Some(chunk) = chunks.last_mut() {
+                    chunk.extend(Some(token))
+                }
+            }
+        }
+    }
+
+    chunks.retain(|x| !x.is_empty());
+    chunks
+}
+
+fn expand_attr_tokens(
+    tokens: TokenStream,
+    cfg: &[Cfg],
+    file: &Path,
+    expanded: &mut Vec<ExpandedAttr>,
+    errors: &mut Vec<Error>,
+) {
+    let mut iter = tokens.clone().into_iter();
+
+    let (span, args) = match (iter.next(), iter.next(), iter.next()) {
+        (Some(TokenTree::Ident(ident)), Some(TokenTree::Group(group)), None)
+            if ident == "cfg_attr" && group.delimiter() == Delimiter::Parenthesis =>
+        {
+            (ident.span(), group.stream())
+        }
+        _ => {
+            expanded.push(ExpandedAttr {
+                cfg: cfg.to_vec(),
+                tokens,
+            });
+            return;
+        }
+    };
+
+    let mut chunks = split_commas(args).into_iter();
+    let predicate = chunks
+        .next()
+        .and_then(|x| syn::parse2::<NestedMeta>(x).ok())
+        .and_then(|x| Cfg::from_nested_meta(&x));
+
+    match predicate {
+        Some(predicate) => {
+            let mut cfg = cfg.to_vec();
+            cfg.push(predicate);
+
+            for chunk in chunks {
+                expand_attr_tokens(chunk, &cfg, file, expanded, errors);
+            }
+        }
+        None => errors.push(Error::InvalidCfg(
+            tokens.to_string(),
+            Location::new(file, span),
+        )),
+    }
+}
+
+/// Expands (possibly nested) `cfg_attr`s into the attributes they may apply.
+/// Other attributes are returned as is, with no predicates
+pub(crate) fn expand_attr(attr: &Attribute, file: &Path) -> (Vec<ExpandedAttr>, Vec<Error>) {
+    let mut tokens = attr.path.to_token_stream();
+    tokens.extend(attr.tokens.clone());
+
+    let mut expanded = vec![];
+    let mut errors = vec![];
+    expand_attr_tokens(tokens, &[], file, &mut expanded, &mut errors);
+
+    (expanded, errors)
+}
+
+fn write_list(f: &mut fmt::Formatter, name: &str, predicates: &[Cfg]) -> fmt::Result {
+    write!(f, "{}(", name)?;
+
+    for (i, predicate) in predicates.iter().enumerate() {
+        if i != 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{}", predicate)?;
+    }
+
+    write!(f, ")")
+}
+
+impl fmt::Display for Cfg {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Flag(name) => write!(f, "{}", name),
+            Self::KeyValue(key, value) => write!(f, "{} = {:?}", key, value),
+            Self::All(predicates) => write_list(f, "all", predicates),
+            Self::Any(predicates) => write_list(f, "any", predicates),
+            Self::Not(predicate) => write!(f, "not({})", predicate),
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use std::collections::HashMap;
+
+use syn::{punctuated::Punctuated, token::Comma, Expr, Lit, LitStr, Macro};
+
+use crate::common::ToTokenString;
+
+/// Value of a constant expression allowed in `include!`-like macro arguments
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Str(String),
+    /// Result of `option_env!`
+    OptionStr(Option<String>),
+}
+
+/// Evaluates string literals and `concat!`, `env!`, `option_env!` and `stringify!`
+/// invocations, looking environment variables up in `env`. Returns `None` when the
+/// expression is not supported or refers to unknown variables
+pub fn eval(expr: &Expr, env: &HashMap<String, String>) -> Option<Value> {
+    match expr {
+        Expr::Lit(expr_lit) => eval_lit(&expr_lit.lit).map(Value::Str),
+        Expr::Group(group) => eval(&group.expr, env),
+        Expr::Paren(paren) => eval(&paren.expr, env),
+        Expr::Macro(expr_macro) => eval_macro(&expr_macro.mac, env),
+        _ => None,
+    }
+}
+
+/// Like `eval`, but only accepts expressions evaluating to a string
+pub fn eval_str(expr: &Expr, env: &HashMap<String, String>) -> Option<String> {
+    match eval(expr, env)? {
+        Value::Str(value) => Some(value),
+        Value::OptionStr(_) => None,
+    }
+}
+
+fn eval_lit(lit: &Lit) -> Option<String> {
+    match lit {
+        Lit::Str(lit) => Some(lit.value()),
+        Lit::Char(lit) => Some(lit.value().to_string()),
+        Lit::Int(lit) => Some(lit.base10_digits().to_owned()),
+        Lit::Float(lit) => Some(lit.base10_digits().to_owned()),
+        Lit::Bool(lit) => Some(lit.value.to_string()),
+        _ => None,
+    }
+}
+
+fn eval_macro(mac: &Macro, env: &HashMap<String, String>) -> Option<Value> {
+    let name = mac.path.segments.last()?.ident.to_string();
+
+    match name.as_str() {
+        "concat" => {
+            let args = mac
+                .parse_body_with(Punctuated::<Expr, Comma>::parse_terminated)
+                .ok()?;
+
+            args.iter()
+                .map(|arg| eval_str(arg, env))
+                .collect::<Option<String>>()
+                .map(Value::Str)
+        }
+        "env" => {
+            let args = mac
+                .parse_body_with(Punctuated::<LitStr, Comma>::parse_terminated)
+                .ok()?;
+
+            env.get(&args.first()?.value()).cloned().map(Value::Str)
+        }
+        "option_env" => {
+            let name = mac.parse_body::<LitStr>().ok()?;
+            Some(Value::OptionStr(env.get(&name.value()).cloned()))
+        }
+        "stringify" => Some(Value::Str(mac.tokens.to_token_string())),
+        _ => None,
+    }
+}
@@ -0,0 +1,331 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::error::{Error, SourcesAndErrors};
+use crate::location::Location;
+use crate::source_desc::SourceFileDesc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// Compiler-style report of one problem. `Display` prints it the way rustc
+/// does, with the source line under the location if the file could be read
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// rustc error code, e.g. `E0583`
+    pub code: Option<&'static str>,
+    pub message: String,
+    pub location: Option<Location>,
+    /// Line `location` points at, read when the diagnostic is built
+    pub source_line: Option<String>,
+    pub notes: Vec<String>,
+    pub help: Option<String>,
+}
+
+impl Diagnostic {
+    fn error(message: String, location: Option<Location>) -> Self {
+        Diagnostic {
+            severity: Severity::Error,
+            code: None,
+            message,
+            location,
+            source_line: None,
+            notes: vec![],
+            help: None,
+        }
+    }
+
+    /// Reads the source line of the location from `file`, the file it was found in
+    fn with_source_line(mut self, file: &Path) -> Self {
+        self.source_line = self
+            .location
+            .as_ref()
+            .and_then(|location| source_line(file, location.line));
+        self
+    }
+
+    /// Source line of the diagnostic, read from the file reported paths in
+    /// `result` stand for
+    fn with_source_line_in(self, result: &SourcesAndErrors) -> Self {
+        match &self.location {
+            Some(location) => {
+                let file = result.read_path(&location.file).to_path_buf();
+                self.with_source_line(&file)
+            }
+            None => self,
+        }
+    }
+
+    /// Builds diagnostics for all errors in `result`. Candidate files of a module
+    /// declaration that were all missing are reported as one unresolved module
+    pub fn from_errors(result: &SourcesAndErrors) -> Vec<Diagnostic> {
+        let mut diagnostics = vec![];
+
+        for (desc, errors) in &result.sources {
+            let mut missing: Vec<&SourceFileDesc> = vec![];
+
+            for error in errors {
+                if let Error::MissingFile(candidate) = error {
+                    let same_declaration = missing
+                        .first()
+                        .is_none_or(|x| declaration(x) == declaration(candidate));
+
+                    if !same_declaration {
+                        diagnostics.push(Self::missing_files(&missing).with_source_line_in(result));
+                        missing.clear();
+                    }
+
+                    missing.push(candidate);
+                    continue;
+                }
+
+                if !missing.is_empty() {
+                    diagnostics.push(Self::missing_files(&missing).with_source_line_in(result));
+                    missing.clear();
+                }
+
+                diagnostics.push(Self::describe(error, desc).with_source_line_in(result));
+            }
+
+            if !missing.is_empty() {
+                diagnostics.push(Self::missing_files(&missing).with_source_line_in(result));
+            }
+        }
+
+        diagnostics
+    }
+
+    /// Diagnostic for a single error found in `desc`, with paths as they were read
+    pub fn from_error(error: &Error, desc: &SourceFileDesc) -> Self {
+        let diagnostic = Self::describe(error, desc);
+
+        match error.location() {
+            Some(location) => diagnostic.with_source_line(&location.file),
+            None => diagnostic,
+        }
+    }
+
+    fn describe(error: &Error, desc: &SourceFileDesc) -> Self {
+        let location = error.location().cloned();
+
+        match error {
+            Error::Syn(cause, _) => Self::error(format!("failed to parse: {}", cause), location),
+            Error::IO(cause) => Self::error(
+                format!("couldn't read `{}`: {}", desc.path.display(), cause),
+                location,
+            ),
+            Error::UnresolvedPathAttr(attr, _) => {
+                let mut diagnostic = Self::error("unresolved path attribute".to_owned(), location);
+                diagnostic.notes.push(format!("in `{}`", attr));
+                diagnostic
+            }
+            Error::UnresolvedIncludeArg(include, _) => {
+                let mut diagnostic =
+                    Self::error("unresolved include argument".to_owned(), location);
+                diagnostic.notes.push(format!("in `{}`", include));
+                diagnostic.help = Some(
+                    "variables used by `env!` can be set with `Options::env` or `--env`".to_owned(),
+                );
+                diagnostic
+            }
+            Error::InvalidCfg(cfg, _) => {
+                Self::error(format!("invalid cfg predicate in `{}`", cfg), location)
+            }
+            Error::MalformedCfgIf(cause, _) => Self::error(
+                format!("malformed `cfg_if!` invocation: {}", cause),
+                location,
+            ),
+            Error::MissingFile(candidate) => Self::missing_files(&[candidate]),
+            Error::AmbiguousModule(name, paths, _) => {
+                let paths: Vec<_> = paths.iter().map(|x| format!("`{}`", x.display())).collect();
+                let mut diagnostic = Self::error(
+                    format!(
+                        "file for module `{}` found at both {}",
+                        name,
+                        join_or_list(&paths)
+                    ),
+                    location,
+                );
+                diagnostic.code = Some("E0761");
+                diagnostic.help =
+                    Some("delete or rename one of them to remove the ambiguity".to_owned());
+                diagnostic
+            }
+            Error::NonAsciiModuleName(name, _) => {
+                let mut diagnostic = Self::error(
+                    format!(
+                        "trying to load file for module `{}` with non-ascii identifier name",
+                        name
+                    ),
+                    location,
+                );
+                diagnostic.code = Some("E0754");
+                diagnostic.help = Some(
+                    "consider using the `#[path]` attribute to specify filesystem path".to_owned(),
+                );
+                diagnostic
+            }
+            Error::ModuleInBlock(name, _) => {
+                let mut diagnostic = Self::error(
+                    "cannot declare a file module inside a block unless it has a path attribute"
+                        .to_owned(),
+                    location,
+                );
+                diagnostic.notes.push(
+                    "file modules are usually placed outside of blocks, at the top level of the file"
+                        .to_owned(),
+                );
+                diagnostic.help = Some(format!(
+                    "maybe `use` the module `{}` instead of redeclaring it",
+                    name
+                ));
+                diagnostic
+            }
+            Error::InvalidPath(path, _) => Self::error(
+                format!(
+                    "`{}` has no parent directory or file name to resolve modules against",
+                    path.display()
+                ),
+                location,
+            ),
+            Error::Cycle(chain, _) => {
+                let mut diagnostic = Self::error("inclusion cycle".to_owned(), location);
+                let chain: Vec<_> = chain.iter().map(|x| x.display().to_string()).collect();
+                diagnostic.notes.push(chain.join(" -> "));
+                diagnostic
+            }
+        }
+    }
+
+    /// Diagnostic for files a single declaration could be resolved to, none of which exist
+    fn missing_files(candidates: &[&SourceFileDesc]) -> Self {
+        let inclusion = candidates.first().and_then(|x| x.parents.first());
+        let location = inclusion.map(|x| x.location.clone());
+        let paths: Vec<_> = candidates
+            .iter()
+            .map(|x| format!("`{}`", x.path.display()))
+            .collect();
+
+        let module = match (inclusion, candidates.first()) {
+            (Some(inclusion), Some(desc)) if inclusion.declaration.starts_with("mod ") => {
+                desc.module_path.last()
+            }
+            _ => None,
+        };
+
+        match module {
+            Some(module) => {
+                let mut diagnostic =
+                    Self::error(format!("unresolved module `{}`", module), location);
+                diagnostic.code = Some("E0583");
+                diagnostic
+                    .notes
+                    .push(format!("tried {}", join_or_list(&paths)));
+                let files = if paths.len() == 1 {
+                    "this file"
+                } else {
+                    "one of these files"
+                };
+                diagnostic.help = Some(format!(
+                    "to create the module `{}`, create {}, \
+                     or point to an existing one with `#[path = \"...\"]`",
+                    module, files
+                ));
+                diagnostic
+            }
+            None => Self::error(format!("couldn't read {}", join_or_list(&paths)), location),
+        }
+    }
+}
+
+/// Declaration pulling `desc` in, with its location
+fn declaration(desc: &SourceFileDesc) -> Option<(&Location, &str)> {
+    let inclusion = desc.parents.first()?;
+    Some((&inclusion.location, &inclusion.declaration))
+}
+
+fn join_or_list(items: &[String]) -> String {
+    match items {
+        [] => String::new(),
+        [item] => item.clone(),
+        [init @ .., last] => format!("{} and {}", init.join(", "), last),
+    }
+}
+
+/// Returns the 1-based `line` of `file`, if the file can be read
+fn source_line(file: &Path, line: usize) -> Option<String> {
+    let content = fs::read_to_string(file).ok()?;
+    let line = content.lines().nth(line.checked_sub(1)?)?;
+    Some(line.trim_end().to_owned())
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.severity)?;
+
+        if let Some(code) = self.code {
+            write!(f, "[{}]", code)?;
+        }
+
+        writeln!(f, ": {}", self.message)?;
+
+        let width = match &self.location {
+            Some(location) => location.line.to_string().len(),
+            None => 0,
+        };
+
+        if let Some(location) = &self.location {
+            writeln!(f, "{:width$}--> {}", "", location, width = width)?;
+
+            if let Some(line) = &self.source_line {
+                // Spans running over several lines are underlined up to the end of the first
+                let byte_index = |column: usize| {
+                    line.char_indices()
+                        .nth(column.saturating_sub(1))
+                        .map_or(line.len(), |(i, _)| i)
+                };
+                let start = byte_index(location.column);
+                let end = if location.end_line == location.line {
+                    byte_index(location.end_column).max(start)
+                } else {
+                    line.len()
+                };
+                let indent: String = line[..start]
+                    .chars()
+                    .map(|x| if x == '\t' { '\t' } else { ' ' })
+                    .collect();
+                let carets = "^".repeat(line[start..end].chars().count().max(1));
+
+                writeln!(f, "{:width$} |", "", width = width)?;
+                writeln!(f, "{} | {}", location.line, line)?;
+                writeln!(f, "{:width$} | {}{}", "", indent, carets, width = width)?;
+            }
+        }
+
+        if !self.notes.is_empty() || self.help.is_some() {
+            writeln!(f, "{:width$} |", "", width = width)?;
+        }
+
+        for note in &self.notes {
+            writeln!(f, "{:width$} = note: {}", "", note, width = width)?;
+        }
+
+        if let Some(help) = &self.help {
+            writeln!(f, "{:width$} = help: {}", "", help, width = width)?;
+        }
+
+        Ok(())
+    }
+}
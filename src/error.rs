@@ -1,23 +1,77 @@
+use crate::location::Location;
+use crate::module_tree::ModuleTree;
 use crate::source_desc::SourceFileDesc;
+use std::collections::HashMap;
 use std::fmt;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug)]
 pub enum Error {
-    Syn(syn::Error),
+    /// Message of the parse error. Only the message is kept, the spans of the
+    /// parsed file do not outlive parsing
+    Syn(String, Location),
     IO(std::io::Error),
-    UnresolvedPathAttr(String),
-    UnresolvedIncludeArg(String),
-    MissingFile(SourceFileDesc),
+    UnresolvedPathAttr(String, Location),
+    UnresolvedIncludeArg(String, Location),
+    InvalidCfg(String, Location),
+    /// Message of the parse error, like for `Syn`
+    MalformedCfgIf(String, Location),
+    /// Located at the declaration in `parents`
+    MissingFile(Box<SourceFileDesc>),
+    /// Chain of files including each other, the first and the last one are the same.
+    /// Located at the declaration closing the cycle
+    Cycle(Vec<PathBuf>, Location),
+    /// Module with more than one candidate file present, e.g. both `foo.rs` and `foo/mod.rs`
+    AmbiguousModule(String, Vec<PathBuf>, Location),
+    /// Module with a non-ASCII name and no `#[path]`, rustc refuses to look for its file
+    NonAsciiModuleName(String, Location),
+    /// Module without `#[path]` declared inside a block, e.g. a function body
+    ModuleInBlock(String, Location),
+    /// Path without the parent directory or file name needed to resolve relative to it,
+    /// e.g. a `#[path]` of `..` or a root directory
+    InvalidPath(PathBuf, Location),
+}
+
+impl Error {
+    /// Where the error occurred, `None` for I/O errors affecting a whole file
+    pub fn location(&self) -> Option<&Location> {
+        match self {
+            Self::Syn(_, location)
+            | Self::UnresolvedPathAttr(_, location)
+            | Self::UnresolvedIncludeArg(_, location)
+            | Self::InvalidCfg(_, location)
+            | Self::MalformedCfgIf(_, location)
+            | Self::Cycle(_, location)
+            | Self::NonAsciiModuleName(_, location)
+            | Self::ModuleInBlock(_, location)
+            | Self::InvalidPath(_, location)
+            | Self::AmbiguousModule(_, _, location) => Some(location),
+            Self::MissingFile(desc) => desc.parents.first().map(|x| &x.location),
+            Self::IO(_) => None,
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct SourcesAndErrors {
     pub sources: Vec<(SourceFileDesc, Vec<Error>)>,
+    pub module_tree: ModuleTree,
+    /// Files as they were read, by the path they are reported as
+    pub(crate) read_paths: HashMap<PathBuf, PathBuf>,
 }
 
 impl SourcesAndErrors {
     pub fn new(sources: Vec<(SourceFileDesc, Vec<Error>)>) -> Self {
-        Self { sources }
+        Self {
+            sources,
+            module_tree: ModuleTree::default(),
+            read_paths: HashMap::new(),
+        }
+    }
+
+    /// Path the file reported as `path` can be read from
+    pub(crate) fn read_path<'a>(&'a self, path: &'a Path) -> &'a Path {
+        self.read_paths.get(path).map_or(path, PathBuf::as_path)
     }
 
     pub fn into_sources(self) -> Vec<SourceFileDesc> {
@@ -62,18 +116,75 @@ impl std::error::Error for SourcesAndErrors {}
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(location) = self.location() {
+            write!(f, "{}: ", location)?;
+        }
+
         match *self {
-            Self::UnresolvedPathAttr(ref path_attr) => {
+            Self::UnresolvedPathAttr(ref path_attr, _) => {
                 write!(f, "Unresolved path attribute in {}", path_attr)
             }
-            Self::UnresolvedIncludeArg(ref include) => {
+            Self::UnresolvedIncludeArg(ref include, _) => {
                 write!(f, "Unresolved include argument in {}", include)
             }
-            Self::MissingFile(ref path) => {
-                write!(f, "File {:?} does not exist or could not be read", path)
+            Self::InvalidCfg(ref cfg, _) => write!(f, "Invalid cfg predicate in {}", cfg),
+            Self::MalformedCfgIf(ref cause, _) => {
+                write!(f, "Malformed cfg_if! invocation: {}", cause)
             }
+            Self::MissingFile(ref desc) => {
+                if let Some(inclusion) = desc.parents.first() {
+                    write!(f, "{}: ", inclusion.declaration)?;
+                }
+
+                write!(
+                    f,
+                    "File {} does not exist or could not be read",
+                    desc.path.display()
+                )
+            }
+            Self::Cycle(ref chain, _) => {
+                write!(f, "Inclusion cycle: ")?;
+
+                for (i, path) in chain.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, " -> ")?;
+                    }
+                    write!(f, "{}", path.display())?;
+                }
+
+                Ok(())
+            }
+            Self::AmbiguousModule(ref name, ref paths, _) => {
+                write!(f, "Module {} found at both ", name)?;
+
+                for (i, path) in paths.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, " and ")?;
+                    }
+                    write!(f, "{}", path.display())?;
+                }
+
+                Ok(())
+            }
+            Self::NonAsciiModuleName(ref name, _) => {
+                write!(
+                    f,
+                    "Module {} has a non-ASCII name and no path attribute",
+                    name
+                )
+            }
+            Self::ModuleInBlock(ref name, _) => write!(
+                f,
+                "Module {} is declared inside a block and has no path attribute",
+                name
+            ),
+            Self::InvalidPath(ref path, _) => write!(
+                f,
+                "Path {} has no parent directory or file name",
+                path.display()
+            ),
             Self::IO(ref cause) => write!(f, "I/O error: {}", cause),
-            Self::Syn(ref cause) => write!(f, "Syn error: {}", cause),
+            Self::Syn(ref cause, _) => write!(f, "Syn error: {}", cause),
         }
     }
 }
@@ -84,7 +195,6 @@ impl std::error::Error for Error {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match *self {
             Self::IO(ref cause) => Some(cause),
-            Self::Syn(ref cause) => Some(cause),
             _ => None,
         }
     }
@@ -95,11 +205,6 @@ impl From<std::io::Error> for Error {
         Self::IO(cause)
     }
 }
-impl From<syn::Error> for Error {
-    fn from(cause: syn::Error) -> Self {
-        Self::Syn(cause)
-    }
-}
 
 impl From<Error> for Vec<Error> {
     fn from(cause: Error) -> Self {
@@ -1,84 +1,352 @@
+pub mod cfg;
 mod common;
+pub mod const_eval;
+mod diagnostic;
 pub mod error;
+mod location;
+mod manifest;
 mod mod_path;
+mod module_tree;
+mod options;
+mod paths;
 mod source_desc;
 mod visitor;
 
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::Read;
+use std::panic;
 use std::path::{Path, PathBuf};
+use std::thread;
 
 use syn::visit::Visit;
 
+pub use cfg::{Cfg, CfgSet};
+pub use diagnostic::{Diagnostic, Severity};
 pub use error::{Error, SourcesAndErrors};
+pub use location::Location;
+pub use manifest::find_out_dir;
 pub use mod_path::ModPath;
-pub use source_desc::{ModType, SourceFileDesc, SourceFileType};
+use module_tree::ModuleRecord;
+pub use module_tree::{ModuleId, ModuleNode, ModuleTree};
+pub use options::{Options, PathStyle, SourceOrder};
+pub use paths::normalize_path;
+use source_desc::Merged;
+pub use source_desc::{Inclusion, ModType, SnippetContext, SourceFileDesc, SourceFileType};
 use visitor::SourceFinder;
 
-fn propagate_parent_file(path: &Path, source_descs_slice: &mut [SourceFileDesc]) {
-    for source_desc in source_descs_slice {
-        source_desc.parent_file = Some(path.to_owned());
+type VisitResult = (Vec<SourceFileDesc>, Vec<Error>, Vec<ModuleRecord>);
+
+/// Syntax tree of a Rust file, parsed as its `SnippetContext` says
+enum Parsed {
+    File(syn::File),
+    Expr(Box<syn::Expr>),
+}
+
+fn parse_source(path: &Path, content: &str, context: SnippetContext) -> Result<Parsed, Error> {
+    let syn_error = |error: syn::Error| {
+        let location = Location::new(path, error.span());
+        Error::Syn(error.to_string(), location)
+    };
+
+    match context {
+        SnippetContext::Items => syn::parse_file(content).map(Parsed::File),
+        SnippetContext::Expr | SnippetContext::Stmts => syn::parse_str(content).map(Parsed::Expr),
     }
+    .map_err(syn_error)
 }
 
-fn visit_source(
-    path: &Path,
-    mut source_finder: SourceFinder,
-) -> Result<(Vec<SourceFileDesc>, Vec<Error>), Error> {
-    let mut file = File::open(&path)?;
+fn read_source(path: &Path, context: SnippetContext) -> Result<Parsed, Error> {
+    let mut file = File::open(path)?;
     let mut content = String::new();
     file.read_to_string(&mut content)?;
-    let ast = syn::parse_file(&content)?;
 
-    source_finder.visit_file(&ast);
+    parse_source(path, &content, context)
+}
 
-    propagate_parent_file(path, &mut source_finder.source_candidates);
+fn visit_parsed(parsed: &Parsed, mut source_finder: SourceFinder) -> VisitResult {
+    match parsed {
+        Parsed::File(file) => source_finder.visit_file(file),
+        Parsed::Expr(expr) => source_finder.visit_expr(expr),
+    }
 
-    Ok((
+    (
         source_finder.source_candidates,
         source_finder.unresolved_items,
-    ))
+        source_finder.module_records,
+    )
 }
 
-pub fn process_source(source: &SourceFileDesc) -> Result<(Vec<SourceFileDesc>, Vec<Error>), Error> {
-    let source_finder = match &source.file_type {
-        SourceFileType::Bytes | SourceFileType::String => return Ok((vec![], vec![])),
-        SourceFileType::RustSnippet(mod_stack) => SourceFinder::new(mod_stack.clone()),
-        SourceFileType::RustSource(mod_type) => {
-            SourceFinder::from_mod_path(ModPath::new(source.path.clone(), *mod_type))
-        }
+/// Visitor set up for the route `source` describes, `None` for non-Rust files
+fn source_finder<'a>(
+    source: &SourceFileDesc,
+    options: &'a Options,
+) -> Option<(SourceFinder<'a>, SnippetContext)> {
+    let (mut source_finder, context) = match &source.file_type {
+        SourceFileType::Bytes | SourceFileType::String => return None,
+        SourceFileType::RustSnippet(mod_stack, context) => (
+            SourceFinder::new(mod_stack.clone(), source.path.clone(), options),
+            *context,
+        ),
+        SourceFileType::RustSource(mod_type) => (
+            SourceFinder::from_mod_path(ModPath::new(source.path.clone(), *mod_type), options),
+            SnippetContext::Items,
+        ),
     };
+    source_finder.cfg_stack.extend(source.cfg.clone());
+    source_finder.heuristic = source.heuristic;
+    source_finder.module_path = source.module_path.clone();
+
+    Some((source_finder, context))
+}
+
+/// Runs `parse` on a thread of its own. proc-macro2 keeps the source text of
+/// parsed files per thread to compute span locations, so this leaves the spans
+/// of the caller alone, and the text is dropped with the thread instead of
+/// growing with every call. Spans are turned into `Location`s before returning
+fn on_parser_thread<T: Send>(parse: impl FnOnce() -> T + Send) -> T {
+    thread::scope(|scope| match scope.spawn(parse).join() {
+        Ok(result) => result,
+        Err(payload) => panic::resume_unwind(payload),
+    })
+}
+
+fn process_parsed(
+    source: &SourceFileDesc,
+    options: &Options,
+    parse: impl FnOnce(SnippetContext) -> Result<Parsed, Error> + Send,
+) -> Result<(Vec<SourceFileDesc>, Vec<Error>), Error> {
+    on_parser_thread(|| match source_finder(source, options) {
+        Some((source_finder, context)) => parse(context).map(|parsed| {
+            let (sources, errors, _) = visit_parsed(&parsed, source_finder);
+            (sources, errors)
+        }),
+        None => Ok((vec![], vec![])),
+    })
+}
+
+/// Returns the files `source` pulls in directly, along with the errors found in it
+pub fn process_source(
+    source: &SourceFileDesc,
+    options: &Options,
+) -> Result<(Vec<SourceFileDesc>, Vec<Error>), Error> {
+    process_parsed(source, options, |context| {
+        read_source(&source.path, context)
+    })
+}
 
-    Ok(visit_source(&source.path, source_finder)?)
+/// Same as `process_source`, with `content` used as the contents of `source`
+/// instead of reading its file. Malformed input is reported as errors
+pub fn process_source_text(
+    source: &SourceFileDesc,
+    content: &str,
+    options: &Options,
+) -> Result<(Vec<SourceFileDesc>, Vec<Error>), Error> {
+    process_parsed(source, options, |context| {
+        parse_source(&source.path, content, context)
+    })
+}
+
+/// Returns all files used to compile the crate with root at `path`, ordered as
+/// `options.order` says. Every file is listed once, and the order depends on
+/// nothing but the sources and `options`
+pub fn crate_srcfiles(
+    path: PathBuf,
+    options: &Options,
+) -> Result<Vec<SourceFileDesc>, SourcesAndErrors> {
+    mod_srcfiles(ModPath::new(path, ModType::ModRs), options)
+}
+
+/// Same as `crate_srcfiles`, for a module at `mod_path`
+pub fn mod_srcfiles(
+    mod_path: ModPath,
+    options: &Options,
+) -> Result<Vec<SourceFileDesc>, SourcesAndErrors> {
+    let result = walk_sources(mod_path, options);
+
+    if result.sources.iter().all(|x| x.1.is_empty()) {
+        Ok(result.into_sources())
+    } else {
+        Err(result)
+    }
+}
+
+/// Returns the module tree of the crate with root at `path`
+pub fn crate_module_tree(path: PathBuf, options: &Options) -> Result<ModuleTree, SourcesAndErrors> {
+    let result = walk_sources(ModPath::new(path, ModType::ModRs), options);
+
+    if result.sources.iter().all(|x| x.1.is_empty()) {
+        Ok(result.module_tree)
+    } else {
+        Err(result)
+    }
 }
 
-pub fn crate_srcfiles(path: PathBuf) -> Result<Vec<SourceFileDesc>, SourcesAndErrors> {
-    mod_srcfiles(ModPath::new(path, ModType::ModRs))
+fn walk_sources(mod_path: ModPath, options: &Options) -> SourcesAndErrors {
+    on_parser_thread(|| walk_sources_in_thread(mod_path, options))
 }
 
-pub fn mod_srcfiles(mod_path: ModPath) -> Result<Vec<SourceFileDesc>, SourcesAndErrors> {
+fn walk_sources_in_thread(mod_path: ModPath, options: &Options) -> SourcesAndErrors {
+    let mut options = options.clone();
+    let root_path = normalize_path(&mod_path.path);
+    let manifest_dir = manifest::find_manifest_dir(&root_path);
+
+    if let Some(manifest_dir) = &manifest_dir {
+        options
+            .env
+            .entry("CARGO_MANIFEST_DIR".to_owned())
+            .or_insert_with(|| manifest_dir.to_string_lossy().into_owned());
+    }
+
+    if let (false, Some(target_dir)) = (options.env.contains_key("OUT_DIR"), &options.target_dir) {
+        let package = options
+            .package
+            .clone()
+            .or_else(|| manifest::package_name(manifest_dir.as_ref()?));
+
+        if let Some(out_dir) = package.and_then(|x| find_out_dir(target_dir, &x)) {
+            options
+                .env
+                .insert("OUT_DIR".to_owned(), out_dir.to_string_lossy().into_owned());
+        }
+    }
+
+    // Include paths are compared with it to tell build script outputs apart
+    if let Some(out_dir) = options.env.get_mut("OUT_DIR") {
+        *out_dir = paths::absolute_path(Path::new(out_dir))
+            .to_string_lossy()
+            .into_owned();
+    }
+
     let mut source_queue = Vec::with_capacity(100);
     let mut result = SourcesAndErrors::new(vec![]);
+    // Index in `result.sources` by canonical path, every file is listed and parsed once
+    let mut source_indices: HashMap<PathBuf, usize> = HashMap::new();
+    // Rust files by canonical path and context, `None` if they could not be parsed
+    let mut parsed_sources: HashMap<(PathBuf, SnippetContext), Option<Parsed>> = HashMap::new();
+    let mut module_records = vec![];
+    let root_module = ModuleNode {
+        path: vec!["crate".to_owned()],
+        file: root_path.clone(),
+        mod_type: Some(mod_path.mod_type),
+        declaration: None,
+        cfg: None,
+        parent: None,
+        children: vec![],
+        includes: vec![],
+    };
 
-    source_queue.push(SourceFileDesc::new(
-        mod_path.path,
-        SourceFileType::RustSource(mod_path.mod_type),
-        None,
+    // Every queued source carries the chain of Rust files leading to it, as
+    // (canonical path, path) pairs, so that recursive includes and modules stop
+    source_queue.push((
+        SourceFileDesc::new(
+            root_path.clone(),
+            SourceFileType::RustSource(mod_path.mod_type),
+        ),
+        vec![],
     ));
 
-    while let Some(source) = source_queue.pop() {
-        match process_source(&source) {
-            Ok((sources, src_errors)) => {
-                source_queue.extend(sources);
-                result.sources.push((source, src_errors));
+    while let Some((source, mut chain)) = source_queue.pop() {
+        let canonical_path = source
+            .path
+            .canonicalize()
+            .unwrap_or_else(|_| source.path.clone());
+        let mut errors = vec![];
+
+        if source.file_type.is_rust() {
+            let start = chain.iter().position(|(x, _)| *x == canonical_path);
+
+            if let (Some(start), Some(inclusion)) = (start, source.parents.last()) {
+                let mut cycle: Vec<_> = chain.drain(start..).map(|(_, path)| path).collect();
+                cycle.push(source.path.clone());
+                errors.push(Error::Cycle(cycle, inclusion.location.clone()));
+            } else {
+                chain.push((canonical_path.clone(), source.path.clone()));
+            }
+        }
+
+        let (index, merged) = match source_indices.get(&canonical_path) {
+            Some(&index) => {
+                let (known_source, known_errors) = &mut result.sources[index];
+
+                // The edge closing a cycle is reported, not followed
+                if !errors.is_empty() {
+                    known_errors.extend(errors);
+                    continue;
+                }
+
+                let merged = known_source.merge(&source);
+                if merged == Merged::Unchanged {
+                    continue;
+                }
+
+                (index, merged)
+            }
+            None => {
+                let visit = errors.is_empty();
+                result.sources.push((source.clone(), errors));
+                source_indices.insert(canonical_path.clone(), result.sources.len() - 1);
+
+                if !visit {
+                    continue;
+                }
+
+                (result.sources.len() - 1, Merged::FirstVisit)
+            }
+        };
+
+        // Every route is visited with its own cfg and module path, so that they
+        // reach the files below it too, but the file is only parsed once
+        let (source_finder, context) = match source_finder(&source, &options) {
+            Some(x) => x,
+            None => continue,
+        };
+
+        let parsed = parsed_sources
+            .entry((canonical_path, context))
+            .or_insert_with(|| match read_source(&source.path, context) {
+                Ok(parsed) => Some(parsed),
+                Err(error) => {
+                    result.sources[index].1.push(error);
+                    None
+                }
+            });
+
+        if let Some(parsed) = parsed {
+            let (sources, src_errors, records) = visit_parsed(parsed, source_finder);
+            // Reversed, so that the stack pops them in declaration order
+            source_queue.extend(sources.into_iter().rev().map(|x| (x, chain.clone())));
+
+            // Other routes find the same errors again, and only new module paths
+            // add modules to the tree
+            if merged == Merged::FirstVisit {
+                result.sources[index].1.extend(src_errors);
+            }
+
+            if merged != Merged::Widened {
+                module_records.extend(records);
             }
-            Err(error) => result.sources.push((source, vec![error])),
         }
     }
 
-    if result.sources.iter().all(|x| x.1.is_empty()) {
-        Ok(result.into_sources())
-    } else {
-        Err(result)
+    result.module_tree = ModuleTree::build(root_module, module_records);
+
+    // Paths are resolved as written, like rustc does, and normalized only here
+    let crate_root = manifest_dir
+        .or_else(|| root_path.parent().map(Path::to_path_buf))
+        .unwrap_or_default();
+
+    let mut read_paths = HashMap::new();
+    paths::map_paths(&mut result, |path| {
+        let styled = paths::style_path(&normalize_path(path), &options.path_style, &crate_root);
+        read_paths.insert(styled.clone(), path.to_path_buf());
+        styled
+    });
+    result.read_paths = read_paths;
+
+    if options.order == SourceOrder::Path {
+        result.sources.sort_by(|x, y| x.0.path.cmp(&y.0.path));
     }
+
+    result
 }
@@ -0,0 +1,36 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use proc_macro2::Span;
+
+/// Span in a source file, lines and columns are 1-based
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    pub file: PathBuf,
+    pub line: usize,
+    pub column: usize,
+    pub end_line: usize,
+    /// Column right after the end of the span
+    pub end_column: usize,
+}
+
+impl Location {
+    pub fn new(file: &Path, span: Span) -> Self {
+        let start = span.start();
+        let end = span.end();
+
+        Location {
+            file: file.to_owned(),
+            line: start.line,
+            column: start.column + 1,
+            end_line: end.line,
+            end_column: end.column + 1,
+        }
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file.display(), self.line, self.column)
+    }
+}
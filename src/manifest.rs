@@ -0,0 +1,77 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Finds the directory of the `Cargo.toml` closest to `path`, as an absolute path
+pub fn find_manifest_dir(path: &Path) -> Option<PathBuf> {
+    let path = env::current_dir().ok()?.join(path);
+
+    path.ancestors()
+        .skip(1)
+        .find(|dir| dir.join("Cargo.toml").is_file())
+        .map(Path::to_path_buf)
+}
+
+/// Reads the package name from the `[package]` section of `manifest_dir/Cargo.toml`
+pub fn package_name(manifest_dir: &Path) -> Option<String> {
+    let manifest = fs::read_to_string(manifest_dir.join("Cargo.toml")).ok()?;
+    let mut in_package = false;
+
+    for line in manifest.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_package = line == "[package]";
+        } else if in_package {
+            if let Some((key, value)) = line.split_once('=') {
+                if key.trim() == "name" {
+                    return Some(value.trim().trim_matches('"').to_owned());
+                }
+            }
+        }
+    }
+
+    None
+}
+
+fn build_dirs(target_dir: &Path) -> Vec<PathBuf> {
+    let mut build_dirs = vec![];
+
+    // target/<profile>/build and target/<triple>/<profile>/build
+    for profile_dir in subdirs(target_dir) {
+        build_dirs.push(profile_dir.join("build"));
+        build_dirs.extend(subdirs(&profile_dir).into_iter().map(|x| x.join("build")));
+    }
+
+    build_dirs.retain(|x| x.is_dir());
+    build_dirs
+}
+
+fn subdirs(dir: &Path) -> Vec<PathBuf> {
+    match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.is_dir())
+            .collect(),
+        Err(_) => vec![],
+    }
+}
+
+/// Finds the most recently modified build script output directory of `package`,
+/// `<target_dir>/<profile>/build/<package>-<hash>/out`, as an absolute path
+pub fn find_out_dir(target_dir: &Path, package: &str) -> Option<PathBuf> {
+    build_dirs(&env::current_dir().ok()?.join(target_dir))
+        .into_iter()
+        .flat_map(|build_dir| subdirs(&build_dir))
+        .filter(|dir| {
+            let file_name = dir.file_name().unwrap_or_default().to_string_lossy();
+            match file_name.rsplit_once('-') {
+                Some((name, hash)) => {
+                    name == package && hash.chars().all(|x| x.is_ascii_hexdigit())
+                }
+                None => false,
+            }
+        })
+        .map(|dir| dir.join("out"))
+        .filter(|out_dir| out_dir.is_dir())
+        .max_by_key(|out_dir| fs::metadata(out_dir).and_then(|x| x.modified()).ok())
+}
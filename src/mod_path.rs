@@ -1,7 +1,10 @@
 use std::path::{Path, PathBuf};
-use syn::{Attribute, Ident, ItemMod, Lit, Meta};
+use syn::ext::IdentExt;
+use syn::{Attribute, ItemMod, Lit, Meta};
 
+use crate::cfg::{expand_attr, Cfg};
 use crate::error::Error;
+use crate::location::Location;
 use crate::source_desc::{ModType, SourceFileDesc, SourceFileType};
 
 #[derive(Debug, Clone)]
@@ -16,9 +19,9 @@ impl ModPath {
     }
 }
 
-impl Into<SourceFileDesc> for ModPath {
-    fn into(self) -> SourceFileDesc {
-        SourceFileDesc::new(self.path, SourceFileType::RustSource(self.mod_type), None)
+impl From<ModPath> for SourceFileDesc {
+    fn from(mod_path: ModPath) -> Self {
+        SourceFileDesc::new(mod_path.path, SourceFileType::RustSource(mod_path.mod_type))
     }
 }
 
@@ -26,10 +29,34 @@ impl Into<SourceFileDesc> for ModPath {
 #[derive(Debug, Clone)]
 pub enum ModSegment {
     InlinePath(PathBuf),
-    Ident(Ident),
+    /// Module name, unraw'd: `mod r#type;` is looked for in `type.rs`
+    Ident(String),
     ModPath(ModPath),
+    /// Block such as a function body. Like in rustc, modules inside it only
+    /// resolve with `#[path]`, and the file name of a non-`mod.rs` file is not
+    /// added to the directory of inline modules in it
+    Block,
 }
 
+/// Segments from the closest file down to the module being resolved. Files are
+/// resolved the way rustc does it:
+///
+/// 1. Every file has a directory, the one containing it. A non-`mod.rs` file
+///    `foo.rs` also has a pending offset `foo`. Crate roots, `mod.rs` files,
+///    `#[path]` files and `include!`d files have none.
+/// 2. An inline `mod a { ... }` takes the directory of its parent, plus the
+///    pending offset (which is consumed), plus `a`. With `#[path = "p"]` it takes
+///    the parent directory joined with `p` instead, and the offset is dropped.
+/// 3. An out-of-line `mod a;` is `<dir>/<offset>/a.rs` or `<dir>/<offset>/a/mod.rs`,
+///    it is an error if both or none exist. With `#[path = "p"]` it is `<dir>/p`,
+///    without the offset. Identifiers are unraw'd, non-ASCII ones need `#[path]`.
+/// 4. Blocks such as function bodies drop the offset, and out-of-line modules
+///    inside them need `#[path]` unless an inline `#[path]` module or an
+///    `include!`d file is entered first.
+/// 5. `include!`d files get their own directory, as if they were `mod.rs` files.
+///
+/// Paths are joined as written and only normalized for output, so like for the
+/// OS, `a/../b.rs` needs the directory `a` and `..` follows symlinks.
 #[derive(Debug, Clone)]
 pub struct ModStack(Vec<ModSegment>);
 
@@ -42,68 +69,115 @@ impl ModStack {
         self.0.pop();
     }
 
-    pub fn parent_file_path(&self) -> &Path {
-        match self.0.first().expect("Using empty modstack") {
-            ModSegment::ModPath(ModPath { path, .. }) => &path,
-            _ => panic!("Invalid ModStack structure"),
+    /// File of the closest file-backed module
+    pub fn mod_file(&self) -> Option<&PathBuf> {
+        match self.0.first() {
+            Some(ModSegment::ModPath(mod_path)) => Some(&mod_path.path),
+            _ => None,
         }
     }
 
-    fn get_mod_path_candidates(&self) -> Vec<ModPath> {
-        if self.0.is_empty() {
-            return vec![];
+    /// Whether out-of-line modules without `#[path]` may be declared here, i.e.
+    /// no block was entered after the last file or inline `#[path]` module
+    fn is_owned(&self) -> bool {
+        for segment in self.0.iter().rev() {
+            match segment {
+                ModSegment::Block => return false,
+                ModSegment::InlinePath(_) | ModSegment::ModPath(_) => return true,
+                ModSegment::Ident(_) => {}
+            }
         }
 
+        true
+    }
+
+    fn get_mod_path_candidates(&self, location: &Location) -> Result<Vec<ModPath>, Error> {
+        let (last, init) = match self.0.split_last() {
+            Some(x) => x,
+            None => return Ok(vec![]),
+        };
+
+        let invalid = |path: &Path| Error::InvalidPath(path.to_path_buf(), location.clone());
         let mut path = PathBuf::new();
 
-        for i in 0..self.0.len() - 1 {
-            match &self.0[i] {
-                ModSegment::Ident(ident) => path.push(ident.to_string()),
+        for (i, segment) in init.iter().enumerate() {
+            match segment {
+                ModSegment::Ident(name) => path.push(name),
                 ModSegment::InlinePath(inline_path) => path.push(inline_path),
+                ModSegment::Block => {}
                 ModSegment::ModPath(mod_path) => {
-                    path.push(mod_path.path.parent().unwrap());
-                    if let (ModSegment::Ident(_), ModType::Adjacent) =
-                        (&self.0[i + 1], mod_path.mod_type)
+                    let parent = mod_path
+                        .path
+                        .parent()
+                        .ok_or_else(|| invalid(&mod_path.path))?;
+                    path = parent.to_path_buf();
+                    if let (Some(ModSegment::Ident(_)), ModType::Adjacent) =
+                        (self.0.get(i + 1), mod_path.mod_type)
                     {
-                        path.push(mod_path.path.file_stem().unwrap());
+                        let stem = mod_path
+                            .path
+                            .file_stem()
+                            .ok_or_else(|| invalid(&mod_path.path))?;
+                        path.push(stem);
                     }
                 }
             }
         }
 
-        match self.0.last().unwrap() {
+        Ok(match last {
             ModSegment::ModPath(mod_path) => {
                 path.push(&mod_path.path);
                 vec![ModPath::new(path, mod_path.mod_type)]
             }
-            ModSegment::Ident(ident) => {
-                let ident = ident.to_string();
-
-                let mut adjacent_candidate = path.join(&ident);
+            ModSegment::Ident(name) => {
+                let mut adjacent_candidate = path.join(name);
                 adjacent_candidate.set_extension("rs");
                 let adjacent_candidate = ModPath::new(adjacent_candidate, ModType::Adjacent);
-                let mut mod_rs_candidate = path.join(&ident);
+                let mut mod_rs_candidate = path.join(name);
                 mod_rs_candidate.push("mod.rs");
                 let mod_rs_candidate = ModPath::new(mod_rs_candidate, ModType::ModRs);
                 vec![adjacent_candidate, mod_rs_candidate]
             }
-            _ => unreachable!(),
-        }
+            // Only modules are resolved, the visitor never asks for these
+            ModSegment::InlinePath(_) | ModSegment::Block => return Err(invalid(&path)),
+        })
     }
 
-    pub fn resolve_mod_path(&self) -> Result<ModPath, Vec<Error>> {
-        let candidates = self.get_mod_path_candidates();
+    /// Resolves the module on top of the stack, declared at `location`. Like rustc,
+    /// rejects modules with both `foo.rs` and `foo/mod.rs` present, and modules
+    /// in blocks or with non-ASCII names that have no `#[path]`
+    pub fn resolve_mod_path(&self, location: &Location) -> Result<ModPath, Vec<Error>> {
+        if let Some(ModSegment::Ident(name)) = self.0.last() {
+            if !self.is_owned() {
+                return Err(vec![Error::ModuleInBlock(name.clone(), location.clone())]);
+            }
 
-        for i in &candidates {
-            if i.path.is_file() {
-                return Ok(i.clone());
+            if !name.is_ascii() {
+                return Err(vec![Error::NonAsciiModuleName(
+                    name.clone(),
+                    location.clone(),
+                )]);
             }
         }
 
+        let candidates = self.get_mod_path_candidates(location)?;
+        let existing: Vec<_> = candidates.iter().filter(|x| x.path.is_file()).collect();
+
+        match (existing.as_slice(), self.0.last()) {
+            ([_, _, ..], Some(ModSegment::Ident(name))) => {
+                return Err(vec![Error::AmbiguousModule(
+                    name.clone(),
+                    existing.iter().map(|x| x.path.clone()).collect(),
+                    location.clone(),
+                )])
+            }
+            ([first, ..], _) => return Ok((*first).clone()),
+            _ => {}
+        }
+
         Err(candidates
             .into_iter()
-            .map(|path| path.into())
-            .map(Error::MissingFile)
+            .map(|path| Error::MissingFile(Box::new(path.into())))
             .collect())
     }
 }
@@ -114,45 +188,78 @@ impl From<Vec<ModSegment>> for ModStack {
     }
 }
 
-fn parse_possible_path(attr: &Attribute) -> Option<Result<PathBuf, Error>> {
-    if let Ok(Meta::NameValue(name_value)) = attr.parse_meta() {
-        let attr_path = &name_value.path.segments;
-        if attr_path.len() == 1 && attr_path.first().unwrap().ident == "path" {
-            if let Lit::Str(path_value) = name_value.lit {
-                return Some(Ok(path_value.value().into()));
-            }
-        }
-    }
+/// Paths `attr` may set, with their predicates. Expansion errors are left to the
+/// visitor, which reports them for every attribute
+fn parse_possible_paths(attr: &Attribute, file: &Path) -> Vec<(Vec<Cfg>, PathBuf)> {
+    let (expanded, _) = expand_attr(attr, file);
 
-    None
+    expanded
+        .into_iter()
+        .flat_map(|expanded_attr| match syn::parse2(expanded_attr.tokens) {
+            Ok(Meta::NameValue(name_value)) if name_value.path.is_ident("path") => {
+                match name_value.lit {
+                    Lit::Str(path_value) => Some((expanded_attr.cfg, path_value.value().into())),
+                    _ => None,
+                }
+            }
+            _ => None,
+        })
+        .collect()
 }
 
-pub fn get_possible_segments(item_mod: &ItemMod) -> (Vec<ModSegment>, Vec<Error>) {
-    let possible_path_attrs: Vec<_> = item_mod
+/// Returns the segments a mod statement may push onto the mod stack, each with the
+/// predicates it is conditional on. Paths from `cfg_attr` only apply under their
+/// predicate, so the default path is kept for the case none of them is active.
+/// Like rustc, the first active path wins, later ones also need the preceding
+/// predicates to be false
+pub fn get_possible_segments(item_mod: &ItemMod, file: &Path) -> Vec<(Vec<Cfg>, ModSegment)> {
+    let possible_paths = item_mod
         .attrs
         .iter()
-        .flat_map(parse_possible_path)
-        .collect();
-
-    if possible_path_attrs.is_empty() {
-        (vec![ModSegment::Ident(item_mod.ident.clone())], vec![])
-    } else {
-        let mut segments = Vec::new();
-        let mut unresolved = Vec::new();
-
-        for path_attr in possible_path_attrs {
-            match path_attr {
-                Ok(path) => {
-                    if item_mod.content.is_some() {
-                        segments.push(ModSegment::InlinePath(path))
-                    } else {
-                        segments.push(ModSegment::ModPath(ModPath::new(path, ModType::ModRs)))
-                    }
-                }
-                Err(unresolved_item) => unresolved.push(unresolved_item),
+        .flat_map(|attr| parse_possible_paths(attr, file));
+
+    let mut segments = Vec::new();
+    let mut conditions = Vec::new();
+    let mut is_conditional = true;
+
+    for (cfg, path) in possible_paths {
+        let mut predicates: Vec<_> = conditions
+            .iter()
+            .cloned()
+            .map(|x| Cfg::Not(Box::new(x)))
+            .collect();
+        let condition = Cfg::all(cfg.clone());
+        predicates.extend(cfg);
+
+        if item_mod.content.is_some() {
+            segments.push((predicates, ModSegment::InlinePath(path)))
+        } else {
+            segments.push((
+                predicates,
+                ModSegment::ModPath(ModPath::new(path, ModType::ModRs)),
+            ))
+        }
+
+        match condition {
+            Some(condition) => conditions.push(condition),
+            None => {
+                // Paths after an unconditional one never apply
+                is_conditional = false;
+                break;
             }
         }
+    }
 
-        (segments, unresolved)
+    if is_conditional {
+        let default_cfg = Cfg::any(conditions)
+            .map(|x| Cfg::Not(Box::new(x)))
+            .into_iter()
+            .collect();
+        segments.push((
+            default_cfg,
+            ModSegment::Ident(item_mod.ident.unraw().to_string()),
+        ));
     }
+
+    segments
 }
@@ -0,0 +1,102 @@
+use std::path::{Path, PathBuf};
+
+use crate::cfg::Cfg;
+use crate::location::Location;
+use crate::source_desc::{Inclusion, ModType};
+
+/// Index of a node in `ModuleTree::nodes`
+pub type ModuleId = usize;
+
+#[derive(Debug, Clone)]
+pub struct ModuleNode {
+    /// Fully qualified path, e.g. `["crate", "a", "b"]`
+    pub path: Vec<String>,
+    /// File the module is defined in. For inline modules, the file containing them
+    pub file: PathBuf,
+    /// `None` for inline modules
+    pub mod_type: Option<ModType>,
+    /// `mod` declaration, `None` for the root
+    pub declaration: Option<Location>,
+    /// Combined `#[cfg]` predicate guarding the module
+    pub cfg: Option<Cfg>,
+    pub parent: Option<ModuleId>,
+    pub children: Vec<ModuleId>,
+    /// `include!`, `include_str!` and `include_bytes!` invocations in the module body
+    pub includes: Vec<Inclusion>,
+}
+
+/// Module hierarchy of a crate. Modules declared under mutually exclusive cfgs,
+/// like `#[cfg_attr(unix, path = "unix.rs")]`, get a node per alternative
+#[derive(Debug, Clone, Default)]
+pub struct ModuleTree {
+    /// All modules, the root first
+    pub nodes: Vec<ModuleNode>,
+}
+
+impl ModuleTree {
+    pub fn root(&self) -> Option<&ModuleNode> {
+        self.nodes.first()
+    }
+
+    /// Child modules of `id`, none if there is no such node
+    pub fn children(&self, id: ModuleId) -> impl Iterator<Item = &ModuleNode> {
+        self.nodes
+            .get(id)
+            .into_iter()
+            .flat_map(|x| &x.children)
+            .filter_map(move |&x| self.nodes.get(x))
+    }
+
+    /// Modules defined by `file`: the module it backs and the inline modules in it
+    pub fn modules_in_file<'a>(&'a self, file: &'a Path) -> impl Iterator<Item = &'a ModuleNode> {
+        self.nodes.iter().filter(move |x| x.file == file)
+    }
+
+    fn find(&self, path: &[String], mod_file: &Path, mod_files: &[PathBuf]) -> Option<ModuleId> {
+        (0..self.nodes.len())
+            .rev()
+            .find(|&x| self.nodes[x].path == path && mod_files[x] == mod_file)
+    }
+
+    /// Links modules and includes recorded while visiting files into a tree.
+    /// Every record refers to its parent by module path and by the file of the
+    /// closest file-backed module, which tells apart cfg alternatives
+    pub(crate) fn build(root: ModuleNode, records: Vec<ModuleRecord>) -> Self {
+        let mut tree = ModuleTree { nodes: vec![] };
+        let mut mod_files = vec![root.file.clone()];
+        tree.nodes.push(root);
+
+        for record in records {
+            let parent = match tree.find(&record.parent_path, &record.parent_mod_file, &mod_files) {
+                Some(parent) => parent,
+                None => continue,
+            };
+
+            match record.kind {
+                RecordKind::Module(mut node, mod_file) => {
+                    let id = tree.nodes.len();
+                    node.parent = Some(parent);
+                    tree.nodes[parent].children.push(id);
+                    tree.nodes.push(node);
+                    mod_files.push(mod_file);
+                }
+                RecordKind::Include(inclusion) => tree.nodes[parent].includes.push(inclusion),
+            }
+        }
+
+        tree
+    }
+}
+
+pub(crate) enum RecordKind {
+    /// New module and the file of its closest file-backed module
+    Module(ModuleNode, PathBuf),
+    Include(Inclusion),
+}
+
+/// Module or include found while visiting a single file
+pub(crate) struct ModuleRecord {
+    pub parent_path: Vec<String>,
+    pub parent_mod_file: PathBuf,
+    pub kind: RecordKind,
+}
@@ -0,0 +1,52 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use crate::cfg::CfgSet;
+
+/// Order sources are returned in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SourceOrder {
+    /// Pre-order of the module tree: a file comes right after the file declaring
+    /// it, and files pulled in by one file follow their declaration order
+    #[default]
+    ModuleTree,
+    /// Sorted by path, compared component-wise
+    Path,
+}
+
+/// Form of the paths in the output. All of them are normalized lexically,
+/// without resolving symlinks
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum PathStyle {
+    /// Relative or absolute, like the root path passed in
+    #[default]
+    Normalized,
+    /// Relative to the directory of the nearest `Cargo.toml`, or of the root file
+    /// if there is none
+    RelativeToCrateRoot,
+    RelativeTo(PathBuf),
+    Absolute,
+}
+
+/// Settings controlling how sources of a crate are discovered
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// Configuration `#[cfg]` predicates are evaluated against. When `None`,
+    /// every conditional module and include is followed. The default file of a
+    /// module with only `cfg_attr` paths is then optional, it is not an error if missing
+    pub cfg_set: Option<CfgSet>,
+    /// Look for includes and modules inside bodies of macros with unknown expansion,
+    /// such as `lazy_static!`. Sources found this way are marked as heuristic
+    pub scan_unknown_macros: bool,
+    /// Environment visible to `env!` and `option_env!` in include arguments.
+    /// `CARGO_MANIFEST_DIR` is filled in from the nearest `Cargo.toml` if missing
+    pub env: HashMap<String, String>,
+    /// Cargo target directory to look for build script outputs in when `OUT_DIR`
+    /// is not set in `env`
+    pub target_dir: Option<PathBuf>,
+    /// Package whose build script outputs are used, defaults to the package
+    /// of the nearest `Cargo.toml`
+    pub package: Option<String>,
+    pub order: SourceOrder,
+    pub path_style: PathStyle,
+}
@@ -0,0 +1,134 @@
+use std::env;
+use std::path::{Component, Path, PathBuf};
+
+use crate::error::{Error, SourcesAndErrors};
+use crate::options::PathStyle;
+use crate::source_desc::{Inclusion, SourceFileDesc};
+
+/// Removes `.` components and folds `..` into the preceding component without
+/// touching the filesystem, so symlinks are kept as they are
+pub fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match normalized.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    normalized.pop();
+                }
+                Some(Component::RootDir) | Some(Component::Prefix(_)) => {}
+                _ => normalized.push(".."),
+            },
+            component => normalized.push(component),
+        }
+    }
+
+    if normalized.as_os_str().is_empty() {
+        normalized.push(".");
+    }
+
+    normalized
+}
+
+/// Normalized absolute form of `path`, relative paths are taken from the current directory
+pub(crate) fn absolute_path(path: &Path) -> PathBuf {
+    match env::current_dir() {
+        Ok(current_dir) => normalize_path(&current_dir.join(path)),
+        Err(_) => normalize_path(path),
+    }
+}
+
+/// Lexical path of `path` relative to `base`, using `..` where needed
+fn relative_path(path: &Path, base: &Path) -> PathBuf {
+    let path = absolute_path(path);
+    let base = absolute_path(base);
+
+    let common = path
+        .components()
+        .zip(base.components())
+        .take_while(|(x, y)| x == y)
+        .count();
+
+    let mut relative: PathBuf = base.components().skip(common).map(|_| "..").collect();
+    relative.extend(path.components().skip(common));
+
+    if relative.as_os_str().is_empty() {
+        relative.push(".");
+    }
+
+    relative
+}
+
+/// Converts a normalized path to the requested style. `crate_root` is the
+/// directory `PathStyle::RelativeToCrateRoot` is relative to
+pub fn style_path(path: &Path, style: &PathStyle, crate_root: &Path) -> PathBuf {
+    match style {
+        PathStyle::Normalized => path.to_path_buf(),
+        PathStyle::RelativeToCrateRoot => relative_path(path, crate_root),
+        PathStyle::RelativeTo(base) => relative_path(path, base),
+        PathStyle::Absolute => absolute_path(path),
+    }
+}
+
+fn map_inclusion_paths(inclusion: &mut Inclusion, f: &mut impl FnMut(&Path) -> PathBuf) {
+    inclusion.parent_file = f(&inclusion.parent_file);
+    inclusion.location.file = f(&inclusion.location.file);
+}
+
+fn map_desc_paths(desc: &mut SourceFileDesc, f: &mut impl FnMut(&Path) -> PathBuf) {
+    desc.path = f(&desc.path);
+
+    for inclusion in &mut desc.parents {
+        map_inclusion_paths(inclusion, f);
+    }
+}
+
+/// Applies `f` to every path reported in `result`
+pub fn map_paths(result: &mut SourcesAndErrors, mut f: impl FnMut(&Path) -> PathBuf) {
+    for node in &mut result.module_tree.nodes {
+        node.file = f(&node.file);
+
+        if let Some(declaration) = &mut node.declaration {
+            declaration.file = f(&declaration.file);
+        }
+
+        for inclusion in &mut node.includes {
+            map_inclusion_paths(inclusion, &mut f);
+        }
+    }
+
+    for (desc, errors) in &mut result.sources {
+        map_desc_paths(desc, &mut f);
+
+        for error in errors {
+            match error {
+                Error::MissingFile(desc) => map_desc_paths(desc, &mut f),
+                Error::Cycle(chain, location) => {
+                    for path in chain {
+                        *path = f(path);
+                    }
+                    location.file = f(&location.file);
+                }
+                Error::InvalidPath(path, location) => {
+                    *path = f(path);
+                    location.file = f(&location.file);
+                }
+                Error::AmbiguousModule(_, paths, location) => {
+                    for path in paths {
+                        *path = f(path);
+                    }
+                    location.file = f(&location.file);
+                }
+                Error::Syn(_, location)
+                | Error::UnresolvedPathAttr(_, location)
+                | Error::UnresolvedIncludeArg(_, location)
+                | Error::InvalidCfg(_, location)
+                | Error::NonAsciiModuleName(_, location)
+                | Error::ModuleInBlock(_, location)
+                | Error::MalformedCfgIf(_, location) => location.file = f(&location.file),
+                Error::IO(_) => {}
+            }
+        }
+    }
+}
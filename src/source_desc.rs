@@ -1,33 +1,137 @@
+use crate::cfg::Cfg;
+use crate::location::Location;
 use crate::mod_path::ModStack;
 use std::path::PathBuf;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ModType {
     Adjacent,
     ModRs,
 }
 
+/// Syntactic position of an `include!` invocation, which decides the grammar
+/// the included file is parsed with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SnippetContext {
+    Items,
+    Expr,
+    /// Statement position. Like rustc, which expands `include!` there to a single
+    /// expression statement, the file is parsed as one expression
+    Stmts,
+}
+
 #[derive(Debug, Clone)]
 pub enum SourceFileType {
     RustSource(ModType),
-    RustSnippet(ModStack),
+    RustSnippet(ModStack, SnippetContext),
     Bytes,
     String,
 }
 
+impl SourceFileType {
+    pub fn is_rust(&self) -> bool {
+        matches!(self, Self::RustSource(_) | Self::RustSnippet(..))
+    }
+}
+
+/// Place a source file is pulled in from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Inclusion {
+    pub parent_file: PathBuf,
+    /// Declaration pulling the file in, e.g. `mod foo;` or `include_str!("foo.txt")`
+    pub declaration: String,
+    /// Span of the declaration in `parent_file`
+    pub location: Location,
+}
+
 #[derive(Debug, Clone)]
 pub struct SourceFileDesc {
     pub path: PathBuf,
     pub file_type: SourceFileType,
-    pub parent_file: Option<PathBuf>,
+    /// Every place the file is pulled in from. The crate root only has
+    /// `include_str!` and `include_bytes!` ones, edges closing a cycle are left out
+    pub parents: Vec<Inclusion>,
+    /// Combined `#[cfg]` predicate guarding the file, `None` if it is unconditional
+    pub cfg: Option<Cfg>,
+    /// Found by scanning the body of a macro with unknown expansion, or inside such a source
+    pub heuristic: bool,
+    /// Lies in the build script output directory (`OUT_DIR`)
+    pub build_output: bool,
+    /// Fully qualified path of the module the file implements, e.g. `["crate", "a", "b"]`.
+    /// Snippets and other included files get the path of the module including them
+    pub module_path: Vec<String>,
+    /// Module paths the file is also reached as, e.g. when two `#[path]` modules
+    /// point at it
+    pub other_module_paths: Vec<Vec<String>>,
+}
+
+/// What another route to a known file changed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Merged {
+    Unchanged,
+    /// The cfg is wider or the file is no longer heuristic, files below it have to follow
+    Widened,
+    /// The file is reached as another module too
+    NewModulePath,
+    /// The file has not been visited as a Rust file yet
+    FirstVisit,
 }
 
 impl SourceFileDesc {
-    pub fn new(path: PathBuf, file_type: SourceFileType, parent_file: Option<PathBuf>) -> Self {
+    pub fn new(path: PathBuf, file_type: SourceFileType) -> Self {
         SourceFileDesc {
             path,
             file_type,
-            parent_file,
+            parents: vec![],
+            cfg: None,
+            heuristic: false,
+            build_output: false,
+            module_path: vec!["crate".to_owned()],
+            other_module_paths: vec![],
+        }
+    }
+
+    /// Every module path the file is reached as
+    pub fn module_paths(&self) -> impl Iterator<Item = &Vec<String>> {
+        Some(&self.module_path)
+            .into_iter()
+            .chain(&self.other_module_paths)
+    }
+
+    /// Merges another route to the same file into this one, and tells whether the
+    /// file has to be visited again for it
+    pub(crate) fn merge(&mut self, other: &SourceFileDesc) -> Merged {
+        for inclusion in &other.parents {
+            if !self.parents.contains(inclusion) {
+                self.parents.push(inclusion.clone());
+            }
+        }
+
+        let cfg = match (&self.cfg, &other.cfg) {
+            (Some(Cfg::Any(cfgs)), Some(other_cfg)) if cfgs.contains(other_cfg) => self.cfg.clone(),
+            (Some(cfg), Some(other_cfg)) if cfg == other_cfg => self.cfg.clone(),
+            (Some(cfg), Some(other_cfg)) => Cfg::any(vec![cfg.clone(), other_cfg.clone()]),
+            _ => None,
+        };
+        let widened = cfg != self.cfg || (self.heuristic && !other.heuristic);
+        self.cfg = cfg;
+        self.heuristic &= other.heuristic;
+        self.build_output &= other.build_output;
+
+        let new_module_path = !self.module_paths().any(|x| *x == other.module_path);
+        if new_module_path {
+            self.other_module_paths.push(other.module_path.clone());
+        }
+
+        if !self.file_type.is_rust() && other.file_type.is_rust() {
+            self.file_type = other.file_type.clone();
+            Merged::FirstVisit
+        } else if new_module_path {
+            Merged::NewModulePath
+        } else if widened {
+            Merged::Widened
+        } else {
+            Merged::Unchanged
         }
     }
 }
@@ -1,81 +1,514 @@
+use proc_macro2::Span;
 use std::path::PathBuf;
-use syn::{visit::Visit, ItemMod, LitStr, Macro};
+use syn::{
+    ext::IdentExt,
+    parse::ParseStream,
+    spanned::Spanned,
+    visit::{self, Visit},
+    Arm, Attribute, Block, Expr, ExprBlock, ExprMacro, File, ImplItem, Item, ItemMacro, ItemMod,
+    Local, Macro, MacroDelimiter, Stmt,
+};
 
+use crate::cfg::{expand_attr, Cfg, ExpandedAttr};
 use crate::common::ToTokenString;
+use crate::const_eval;
 use crate::error::Error;
+use crate::location::Location;
 use crate::mod_path::{ModPath, ModSegment, ModStack};
-use crate::source_desc::{SourceFileDesc, SourceFileType};
+use crate::module_tree::{ModuleNode, ModuleRecord, RecordKind};
+use crate::options::Options;
+use crate::paths::absolute_path;
+use crate::source_desc::{Inclusion, ModType, SnippetContext, SourceFileDesc, SourceFileType};
 
 mod cfg_if;
+mod token_scan;
 
 use cfg_if::{CfgExpr, CfgIf};
+use token_scan::ScannedItem;
 
-pub struct SourceFinder {
+pub struct SourceFinder<'a> {
     pub source_candidates: Vec<SourceFileDesc>,
     pub unresolved_items: Vec<Error>,
     pub mod_stack: ModStack,
+    /// File being visited. Differs from the module file for `include!`d snippets
+    pub current_file: PathBuf,
+    /// `#[cfg]` predicates of the enclosing items, cfg_if! branches and modules
+    pub cfg_stack: Vec<Cfg>,
+    /// Syntactic position of the macro being visited
+    pub snippet_context: SnippetContext,
+    /// Set while scanning bodies of unknown macros, marks discovered sources as heuristic
+    pub heuristic: bool,
+    /// Path of the module being visited, starting with `crate`
+    pub module_path: Vec<String>,
+    /// Modules and includes found, for the module tree
+    pub module_records: Vec<ModuleRecord>,
+    pub options: &'a Options,
 }
 
-impl SourceFinder {
-    pub fn from_mod_path(mod_path: ModPath) -> Self {
-        Self::new(vec![ModSegment::ModPath(mod_path)].into())
+impl<'a> SourceFinder<'a> {
+    pub fn from_mod_path(mod_path: ModPath, options: &'a Options) -> Self {
+        let current_file = mod_path.path.clone();
+        Self::new(
+            vec![ModSegment::ModPath(mod_path)].into(),
+            current_file,
+            options,
+        )
     }
 
-    pub fn new(mod_stack: ModStack) -> Self {
+    pub fn new(mod_stack: ModStack, current_file: PathBuf, options: &'a Options) -> Self {
         SourceFinder {
             source_candidates: vec![],
             unresolved_items: vec![],
             mod_stack,
+            current_file,
+            cfg_stack: vec![],
+            snippet_context: SnippetContext::Items,
+            heuristic: false,
+            module_path: vec!["crate".to_owned()],
+            module_records: vec![],
+            options,
         }
     }
 
-    pub fn push(&mut self, result: Result<SourceFileDesc, Vec<Error>>) {
+    pub fn push(
+        &mut self,
+        result: Result<SourceFileDesc, Vec<Error>>,
+        declaration: String,
+        span: Span,
+    ) {
+        let inclusion = Inclusion {
+            parent_file: self.current_file.clone(),
+            declaration,
+            location: self.location(span),
+        };
+
         match result {
-            Ok(source_file_desc) => self.source_candidates.push(source_file_desc),
-            Err(unresolved) => self.unresolved_items.extend(unresolved),
+            Ok(mut source_file_desc) => {
+                source_file_desc.parents.push(inclusion);
+                source_file_desc.cfg = self.current_cfg();
+                source_file_desc.module_path = self.module_path.clone();
+                source_file_desc.heuristic = self.heuristic;
+                source_file_desc.build_output = match self.options.env.get("OUT_DIR") {
+                    Some(out_dir) => absolute_path(&source_file_desc.path).starts_with(out_dir),
+                    None => false,
+                };
+                self.source_candidates.push(source_file_desc);
+            }
+            Err(unresolved) => {
+                for mut error in unresolved {
+                    if let Error::MissingFile(source_file_desc) = &mut error {
+                        source_file_desc.parents.push(inclusion.clone());
+                        source_file_desc.cfg = self.current_cfg();
+                        source_file_desc.heuristic = self.heuristic;
+                        source_file_desc.module_path = self.module_path.clone();
+                    }
+                    self.unresolved_items.push(error);
+                }
+            }
         }
     }
 
-    fn visit_cfg_if(&mut self, node: &CfgIf) {
-        self.visit_block(&node.then_branch);
+    fn record(&mut self, parent_path: Vec<String>, kind: RecordKind) {
+        let parent_mod_file = self.mod_stack.mod_file().cloned().unwrap_or_default();
+        self.module_records.push(ModuleRecord {
+            parent_path,
+            parent_mod_file,
+            kind,
+        });
+    }
+
+    /// Records a module declared by `node`, visiting in `file`
+    fn record_module(&mut self, node: &ItemMod, file: PathBuf, mod_type: Option<ModType>) {
+        let mut path = self.module_path.clone();
+        path.push(node.ident.unraw().to_string());
+        // Inline modules are told apart by the file of the enclosing file-backed module
+        let mod_file = match mod_type {
+            Some(_) => file.clone(),
+            None => self.mod_stack.mod_file().cloned().unwrap_or_default(),
+        };
+        let module = ModuleNode {
+            path,
+            file,
+            mod_type,
+            declaration: Some(self.location(mod_decl_span(node))),
+            cfg: self.current_cfg(),
+            parent: None,
+            children: vec![],
+            includes: vec![],
+        };
+        self.record(
+            self.module_path.clone(),
+            RecordKind::Module(module, mod_file),
+        );
+    }
+
+    fn location(&self, span: Span) -> Location {
+        Location::new(&self.current_file, span)
+    }
+
+    pub fn current_cfg(&self) -> Option<Cfg> {
+        Cfg::all(self.cfg_stack.iter().cloned())
+    }
+
+    fn with_cfg_predicates(&mut self, predicates: Vec<Cfg>, visit: impl FnOnce(&mut Self)) {
+        if let Some(cfg_set) = &self.options.cfg_set {
+            if !predicates.iter().all(|x| x.eval(cfg_set)) {
+                return;
+            }
+        }
+
+        let depth = self.cfg_stack.len();
+        self.cfg_stack.extend(predicates);
+        visit(self);
+        self.cfg_stack.truncate(depth);
+    }
+
+    /// Runs `visit` under the `#[cfg]` attributes in `attrs`, skipping it if they
+    /// are false for the configured cfg set. Malformed predicates are reported
+    /// and treated as enabled
+    fn with_cfg(&mut self, attrs: &[Attribute], visit: impl FnOnce(&mut Self)) {
+        let mut predicates = vec![];
+
+        let file = self.current_file.clone();
+
+        for cfg in attrs.iter().flat_map(|x| Cfg::from_attr(x, &file)) {
+            match cfg {
+                Ok(cfg) => predicates.push(cfg),
+                Err(error) => self.unresolved_items.push(error),
+            }
+        }
+
+        self.with_cfg_predicates(predicates, visit);
+    }
+
+    /// Visits a cfg_if! chain the way the macro expands it: every branch is
+    /// guarded by its own predicate and the negation of all preceding ones
+    fn visit_cfg_if(&mut self, node: &CfgIf, mut preceding: Vec<Cfg>) {
+        let cfg = match Cfg::from_attr(&node.cfg_attr, &self.current_file) {
+            Some(Ok(cfg)) => Some(cfg),
+            Some(Err(error)) => {
+                self.unresolved_items.push(error);
+                None
+            }
+            None => {
+                self.unresolved_items.push(Error::InvalidCfg(
+                    node.cfg_attr.to_token_string(),
+                    self.location(node.cfg_attr.pound_token.span),
+                ));
+                None
+            }
+        };
+
+        let mut predicates: Vec<_> = preceding
+            .iter()
+            .cloned()
+            .map(|x| Cfg::Not(Box::new(x)))
+            .collect();
+        predicates.extend(cfg.clone());
+        self.with_cfg_predicates(predicates, |this| this.visit_branch(&node.then_branch));
+
+        preceding.extend(cfg);
 
         if let Some((_, cfg_expr_box)) = &node.else_branch {
             match cfg_expr_box.as_ref() {
-                CfgExpr::Block(block) => self.visit_block(block),
-                CfgExpr::If(cfg_if) => self.visit_cfg_if(cfg_if),
+                CfgExpr::Block(block) => {
+                    let predicates = preceding.into_iter().map(|x| Cfg::Not(Box::new(x)));
+                    self.with_cfg_predicates(predicates.collect(), |this| this.visit_branch(block));
+                }
+                CfgExpr::If(cfg_if) => self.visit_cfg_if(cfg_if, preceding),
             }
         }
     }
 
+    /// Visits a cfg_if! branch. Its braces are not a block, the contents end up
+    /// where the macro is invoked and macros in it expand in the same context
+    fn visit_branch(&mut self, node: &Block) {
+        for stmt in &node.stmts {
+            self.visit_stmt_in(stmt, self.snippet_context);
+        }
+    }
+
+    /// Visits a statement, macros in it expand in `snippet_context`
+    fn visit_stmt_in(&mut self, node: &Stmt, snippet_context: SnippetContext) {
+        let (attrs, mac) = match node {
+            Stmt::Item(Item::Macro(item_macro)) => (&item_macro.attrs, &item_macro.mac),
+            Stmt::Expr(Expr::Macro(expr_macro)) | Stmt::Semi(Expr::Macro(expr_macro), _) => {
+                (&expr_macro.attrs, &expr_macro.mac)
+            }
+            _ => return visit::visit_stmt(self, node),
+        };
+
+        self.with_cfg(attrs, |this| {
+            for attr in attrs {
+                this.visit_attribute(attr);
+            }
+
+            this.visit_macro_in(mac, snippet_context)
+        });
+    }
+
+    fn visit_macro_in(&mut self, node: &Macro, snippet_context: SnippetContext) {
+        let outer_context = std::mem::replace(&mut self.snippet_context, snippet_context);
+        self.visit_macro(node);
+        self.snippet_context = outer_context;
+    }
+
+    /// Best-effort search for sources in the body of a macro with unknown expansion:
+    /// the body is tried as items, an expression and statements, falling back to
+    /// a raw token scan. Errors are dropped, since the body may be transformed
+    /// arbitrarily and the tokens need not end up in the expansion at all
+    fn scan_unknown_macro(&mut self, node: &Macro) {
+        let was_heuristic = std::mem::replace(&mut self.heuristic, true);
+        let error_count = self.unresolved_items.len();
+
+        if let Ok(items) = node.parse_body_with(parse_items) {
+            for item in &items {
+                self.visit_item(item);
+            }
+        } else if let Ok(expr) = node.parse_body::<Expr>() {
+            self.visit_expr(&expr);
+        } else if let Ok(stmts) = node.parse_body_with(Block::parse_within) {
+            for stmt in &stmts {
+                self.visit_stmt(stmt);
+            }
+        } else {
+            for scanned in token_scan::scan_tokens(node.tokens.clone()) {
+                match scanned {
+                    ScannedItem::Macro(call) => self.visit_macro(&call),
+                    ScannedItem::Mod(item_mod) => self.visit_item(&Item::Mod(item_mod)),
+                }
+            }
+        }
+
+        self.unresolved_items.truncate(error_count);
+        self.heuristic = was_heuristic;
+    }
+
     pub fn process_cfg_if(&mut self, node: &Macro) {
-        let cfg_if = node.parse_body::<CfgIf>().unwrap();
-        self.visit_cfg_if(&cfg_if);
+        match node.parse_body::<CfgIf>() {
+            Ok(cfg_if) => self.visit_cfg_if(&cfg_if, vec![]),
+            Err(error) => {
+                let location = self.location(error.span());
+                self.unresolved_items
+                    .push(Error::MalformedCfgIf(error.to_string(), location));
+            }
+        }
+    }
+}
+
+fn parse_items(input: ParseStream) -> syn::Result<Vec<Item>> {
+    let mut items = vec![];
+
+    while !input.is_empty() {
+        items.push(input.parse()?);
+    }
+
+    Ok(items)
+}
+
+fn item_attrs(item: &Item) -> &[Attribute] {
+    match item {
+        Item::Const(item) => &item.attrs,
+        Item::Enum(item) => &item.attrs,
+        Item::ExternCrate(item) => &item.attrs,
+        Item::Fn(item) => &item.attrs,
+        Item::ForeignMod(item) => &item.attrs,
+        Item::Impl(item) => &item.attrs,
+        Item::Macro(item) => &item.attrs,
+        Item::Macro2(item) => &item.attrs,
+        Item::Mod(item) => &item.attrs,
+        Item::Static(item) => &item.attrs,
+        Item::Struct(item) => &item.attrs,
+        Item::Trait(item) => &item.attrs,
+        Item::TraitAlias(item) => &item.attrs,
+        Item::Type(item) => &item.attrs,
+        Item::Union(item) => &item.attrs,
+        Item::Use(item) => &item.attrs,
+        _ => &[],
+    }
+}
+
+fn impl_item_attrs(impl_item: &ImplItem) -> &[Attribute] {
+    match impl_item {
+        ImplItem::Const(item) => &item.attrs,
+        ImplItem::Method(item) => &item.attrs,
+        ImplItem::Type(item) => &item.attrs,
+        ImplItem::Macro(item) => &item.attrs,
+        _ => &[],
     }
 }
 
-impl<'ast> Visit<'ast> for SourceFinder {
+/// Span of `mod name;`, or of the `mod name` header of an inline module
+fn mod_decl_span(node: &ItemMod) -> Span {
+    let end = match &node.semi {
+        Some(semi) => semi.span,
+        None => node.ident.span(),
+    };
+    node.mod_token.span.join(end).unwrap_or(node.mod_token.span)
+}
+
+/// Span of a macro invocation from its path to its closing delimiter
+fn macro_span(node: &Macro) -> Span {
+    let end = match &node.delimiter {
+        MacroDelimiter::Paren(paren) => paren.span,
+        MacroDelimiter::Brace(brace) => brace.span,
+        MacroDelimiter::Bracket(bracket) => bracket.span,
+    };
+    let start = node.path.span();
+    start.join(end).unwrap_or(start)
+}
+
+impl<'ast, 'a> Visit<'ast> for SourceFinder<'a> {
+    fn visit_file(&mut self, node: &'ast File) {
+        self.with_cfg(&node.attrs, |this| visit::visit_file(this, node));
+    }
+
+    fn visit_item(&mut self, node: &'ast Item) {
+        self.with_cfg(item_attrs(node), |this| visit::visit_item(this, node));
+    }
+
+    fn visit_impl_item(&mut self, node: &'ast ImplItem) {
+        self.with_cfg(impl_item_attrs(node), |this| {
+            visit::visit_impl_item(this, node)
+        });
+    }
+
+    fn visit_local(&mut self, node: &'ast Local) {
+        self.with_cfg(&node.attrs, |this| visit::visit_local(this, node));
+    }
+
+    fn visit_arm(&mut self, node: &'ast Arm) {
+        self.with_cfg(&node.attrs, |this| visit::visit_arm(this, node));
+    }
+
+    /// Modules in blocks lose the directory of the enclosing file, see `ModSegment::Block`
+    fn visit_block(&mut self, node: &'ast Block) {
+        self.mod_stack.push(ModSegment::Block);
+        visit::visit_block(self, node);
+        self.mod_stack.pop();
+    }
+
+    fn visit_expr_block(&mut self, node: &'ast ExprBlock) {
+        self.with_cfg(&node.attrs, |this| visit::visit_expr_block(this, node));
+    }
+
+    fn visit_expr_macro(&mut self, node: &'ast ExprMacro) {
+        self.with_cfg(&node.attrs, |this| {
+            this.visit_macro_in(&node.mac, SnippetContext::Expr)
+        });
+    }
+
+    fn visit_item_macro(&mut self, node: &'ast ItemMacro) {
+        for attr in &node.attrs {
+            self.visit_attribute(attr);
+        }
+
+        self.visit_macro_in(&node.mac, SnippetContext::Items);
+    }
+
+    /// Macros in statement position expand to statements, this includes a
+    /// trailing macro without semicolon
+    fn visit_stmt(&mut self, node: &'ast Stmt) {
+        self.visit_stmt_in(node, SnippetContext::Stmts);
+    }
+
+    /// Looks for `include_str!`/`include_bytes!` in attribute arguments, as in
+    /// `#![doc = include_str!("../README.md")]`, including ones behind `cfg_attr`
+    fn visit_attribute(&mut self, node: &'ast Attribute) {
+        let (expanded, errors) = expand_attr(node, &self.current_file);
+        self.unresolved_items.extend(errors);
+
+        for ExpandedAttr { cfg, tokens } in expanded {
+            self.with_cfg_predicates(cfg, |this| {
+                for scanned in token_scan::scan_tokens(tokens) {
+                    match scanned {
+                        ScannedItem::Macro(call)
+                            if call.path.is_ident("include_str")
+                                || call.path.is_ident("include_bytes") =>
+                        {
+                            this.visit_macro(&call)
+                        }
+                        _ => {}
+                    }
+                }
+            });
+        }
+    }
+
     fn visit_item_mod(&mut self, node: &'ast ItemMod) {
-        let (possible_segments, unresolved_segments) = crate::mod_path::get_possible_segments(node);
-        self.unresolved_items.extend(unresolved_segments);
+        for attr in &node.attrs {
+            self.visit_attribute(attr);
+        }
+
+        let possible_segments = crate::mod_path::get_possible_segments(node, &self.current_file);
+
+        for (cfg, segment) in possible_segments {
+            // Default path of a module whose paths all come from `cfg_attr`
+            let is_fallback = matches!(segment, ModSegment::Ident(_)) && !cfg.is_empty();
+
+            self.with_cfg_predicates(cfg, |this| {
+                this.mod_stack.push(segment);
+
+                match &node.content {
+                    None => {
+                        let location = this.location(mod_decl_span(node));
+                        let resolved = this.mod_stack.resolve_mod_path(&location);
+
+                        // Without a cfg set there is no telling whether the fallback
+                        // is ever compiled, so it is only followed if its file exists
+                        let optional = is_fallback && this.options.cfg_set.is_none();
+                        match &resolved {
+                            Err(errors)
+                                if optional
+                                    && errors
+                                        .iter()
+                                        .all(|x| matches!(x, Error::MissingFile(_))) =>
+                            {
+                                this.mod_stack.pop();
+                                return;
+                            }
+                            _ => {}
+                        }
 
-        for segment in possible_segments {
-            self.mod_stack.push(segment);
+                        if let Ok(mod_path) = &resolved {
+                            this.record_module(
+                                node,
+                                mod_path.path.clone(),
+                                Some(mod_path.mod_type),
+                            );
+                        }
 
-            match &node.content {
-                None => self.push(self.mod_stack.resolve_mod_path().map(Into::into)),
-                Some((_, items)) => {
-                    for item in items {
-                        self.visit_item(item);
+                        this.module_path.push(node.ident.unraw().to_string());
+                        this.push(
+                            resolved.map(Into::into),
+                            format!("mod {};", node.ident),
+                            mod_decl_span(node),
+                        );
+                        this.module_path.pop();
+                    }
+                    Some((_, items)) => {
+                        this.record_module(node, this.current_file.clone(), None);
+                        this.module_path.push(node.ident.unraw().to_string());
+
+                        for item in items {
+                            this.visit_item(item);
+                        }
+
+                        this.module_path.pop();
                     }
                 }
-            }
 
-            self.mod_stack.pop();
+                this.mod_stack.pop();
+            });
         }
     }
 
     fn visit_macro(&mut self, node: &'ast Macro) {
-        let macro_ident = node.path.segments.last().unwrap().ident.to_string();
+        let macro_ident = match node.path.segments.last() {
+            Some(segment) => segment.ident.to_string(),
+            None => return,
+        };
+        let span = macro_span(node);
 
         if macro_ident.as_str() == "cfg_if" {
             self.process_cfg_if(node);
@@ -85,31 +518,65 @@ impl<'ast> Visit<'ast> for SourceFinder {
         let source_type = match macro_ident.as_str() {
             "include_str" => SourceFileType::String,
             "include_bytes" => SourceFileType::Bytes,
-            "include" => SourceFileType::RustSnippet(self.mod_stack.clone()),
-            _ => return,
+            "include" => SourceFileType::RustSnippet(self.mod_stack.clone(), self.snippet_context),
+            "macro_rules" => return,
+            _ => {
+                if self.options.scan_unknown_macros {
+                    self.scan_unknown_macro(node);
+                }
+                return;
+            }
         };
 
-        let path: PathBuf = match node.parse_body::<LitStr>() {
-            Ok(path) => self
-                .mod_stack
-                .parent_file_path()
-                .parent()
-                .unwrap()
-                .join(path.value()),
-            Err(_) => {
-                self.unresolved_items
-                    .push(Error::UnresolvedIncludeArg(node.to_token_string()));
+        let arg = node
+            .parse_body::<Expr>()
+            .ok()
+            .and_then(|arg| const_eval::eval_str(&arg, &self.options.env));
+
+        let path: PathBuf = match (arg, self.current_file.parent()) {
+            (Some(path), Some(dir)) => dir.join(path),
+            (Some(_), None) => {
+                self.unresolved_items.push(Error::InvalidPath(
+                    self.current_file.clone(),
+                    self.location(span),
+                ));
+                return;
+            }
+            (None, _) => {
+                self.unresolved_items.push(Error::UnresolvedIncludeArg(
+                    node.to_token_string(),
+                    self.location(span),
+                ));
                 return;
             }
         };
 
-        let source_file_desc = SourceFileDesc::new(path, source_type, None);
+        let mut source_type = source_type;
+        if let SourceFileType::RustSnippet(mod_stack, _) = &mut source_type {
+            // Like rustc, modules in an included file resolve relative to it, as in a mod.rs
+            mod_stack.push(ModSegment::ModPath(ModPath::new(
+                path.clone(),
+                ModType::ModRs,
+            )));
+        }
+
+        let source_file_desc = SourceFileDesc::new(path, source_type);
+        let declaration = format!("{}!({})", macro_ident, node.tokens);
+        let inclusion = Inclusion {
+            parent_file: self.current_file.clone(),
+            declaration: declaration.clone(),
+            location: self.location(span),
+        };
+        self.record(self.module_path.clone(), RecordKind::Include(inclusion));
 
         if source_file_desc.path.is_file() {
-            self.source_candidates.push(source_file_desc);
+            self.push(Ok(source_file_desc), declaration, span);
         } else {
-            self.unresolved_items
-                .push(Error::MissingFile(source_file_desc));
+            self.push(
+                Err(vec![Error::MissingFile(Box::new(source_file_desc))]),
+                declaration,
+                span,
+            );
         }
     }
 }
@@ -12,7 +12,6 @@ pub enum CfgExpr {
 
 #[derive(Debug, Clone)]
 pub struct CfgIf {
-    pub if_token: If,
     pub cfg_attr: Attribute,
     pub then_branch: Block,
     pub else_branch: Option<(Else, Box<CfgExpr>)>,
@@ -20,14 +19,14 @@ pub struct CfgIf {
 
 impl Parse for CfgIf {
     fn parse(input: ParseStream) -> Result<Self> {
+        input.parse::<If>()?;
+
         Ok(CfgIf {
-            if_token: input.parse()?,
             cfg_attr: {
-                let v = input.call(Attribute::parse_outer)?;
-                if v.len() == 1 {
-                    v.into_iter().next().unwrap()
-                } else {
-                    return Err(input.error("Wrong number of attrs in cfg_if! condition"));
+                let mut v = input.call(Attribute::parse_outer)?;
+                match (v.pop(), v.is_empty()) {
+                    (Some(attr), true) => attr,
+                    _ => return Err(input.error("Wrong number of attrs in cfg_if! condition")),
                 }
             },
             then_branch: input.parse()?,
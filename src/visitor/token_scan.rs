@@ -0,0 +1,78 @@
+use proc_macro2::{Delimiter, TokenStream, TokenTree};
+use syn::{ItemMod, Macro};
+
+/// Item found by scanning raw tokens
+pub enum ScannedItem {
+    /// `name!(...)` invocation, only the last path segment is kept as the macro path
+    Macro(Macro),
+    /// `mod name;` or `mod name { ... }` with the outer attributes preceding it
+    Mod(ItemMod),
+}
+
+/// Collects macro invocations and module declarations in a token stream that
+/// could not be parsed as Rust syntax, descending into groups. Inline module
+/// bodies are not descended into, they are covered by the declaration itself
+pub fn scan_tokens(tokens: TokenStream) -> Vec<ScannedItem> {
+    let mut scanned = vec![];
+    collect(tokens, &mut scanned);
+    scanned
+}
+
+fn is_punct(token: Option<&TokenTree>, ch: char) -> bool {
+    matches!(token, Some(TokenTree::Punct(punct)) if punct.as_char() == ch)
+}
+
+fn is_ident(token: Option<&TokenTree>, name: &str) -> bool {
+    matches!(token, Some(TokenTree::Ident(ident)) if ident == name)
+}
+
+fn mod_decl(tokens: &[TokenTree], i: usize) -> Option<ItemMod> {
+    let end = match (tokens.get(i + 1), tokens.get(i + 2)) {
+        (Some(TokenTree::Ident(_)), Some(TokenTree::Group(group)))
+            if group.delimiter() == Delimiter::Brace =>
+        {
+            i + 3
+        }
+        (Some(TokenTree::Ident(_)), semi) if is_punct(semi, ';') => i + 3,
+        _ => return None,
+    };
+
+    let mut start = i;
+    while start >= 2 && is_punct(tokens.get(start - 2), '#') {
+        match &tokens[start - 1] {
+            TokenTree::Group(group) if group.delimiter() == Delimiter::Bracket => start -= 2,
+            _ => break,
+        }
+    }
+
+    syn::parse2(tokens[start..end].iter().cloned().collect()).ok()
+}
+
+fn collect(tokens: TokenStream, scanned: &mut Vec<ScannedItem>) {
+    let tokens: Vec<_> = tokens.into_iter().collect();
+
+    for (i, token) in tokens.iter().enumerate() {
+        match token {
+            TokenTree::Ident(ident) if ident == "mod" => {
+                scanned.extend(mod_decl(&tokens, i).map(ScannedItem::Mod))
+            }
+            TokenTree::Ident(ident) if is_punct(tokens.get(i + 1), '!') => {
+                if let Some(TokenTree::Group(group)) = tokens.get(i + 2) {
+                    let bang = &tokens[i + 1];
+                    if let Ok(call) = syn::parse2(quote::quote!(#ident #bang #group)) {
+                        scanned.push(ScannedItem::Macro(call));
+                    }
+                }
+            }
+            TokenTree::Group(group) => {
+                let is_macro_body = i >= 1 && is_punct(tokens.get(i - 1), '!');
+                let is_mod_body = i >= 2 && is_ident(tokens.get(i - 2), "mod");
+
+                if !is_macro_body && !is_mod_body {
+                    collect(group.stream(), scanned);
+                }
+            }
+            _ => {}
+        }
+    }
+}
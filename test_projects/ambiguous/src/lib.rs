@@ -0,0 +1,8 @@
+mod foo;
+
+mod bar {
+    #[path = "../foo.rs"]
+    mod foo;
+}
+
+mod r#type;
@@ -0,0 +1,2 @@
+#[cfg_attr(feature = "x", cfg_attr(docsrs, doc = include_str!("inner.md")))]
+pub struct Documented;
@@ -0,0 +1,5 @@
+#![doc = include_str!("../README.md")]
+#![cfg_attr(docsrs, doc = include_str!("../docs/extra.md"))]
+
+#[doc = include_str!("documented.md")]
+mod documented;
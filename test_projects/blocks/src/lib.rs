@@ -0,0 +1,14 @@
+mod sub;
+
+fn f() {
+    #[path = "x.rs"]
+    mod x;
+
+    mod q;
+}
+
+const C: () = {
+    mod w {
+        mod e;
+    }
+};
@@ -0,0 +1,14 @@
+fn g() {
+    #[path = "z.rs"]
+    mod z;
+
+    mod inl {
+        #[path = "y.rs"]
+        mod y;
+    }
+}
+
+mod inl2 {
+    #[path = "y.rs"]
+    mod y;
+}
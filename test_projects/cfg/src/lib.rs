@@ -0,0 +1,14 @@
+#[cfg(unix)]
+mod unix;
+#[cfg(windows)]
+mod windows;
+#[cfg(all(feature = "tls", not(target_os = "macos")))]
+mod tls;
+
+#[cfg(any(test, feature = "bench"))]
+mod testing {
+    mod helpers;
+}
+
+#[cfg(not(unix))]
+const DATA: &str = include_str!("data.txt");
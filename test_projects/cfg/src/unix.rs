@@ -0,0 +1,2 @@
+#[cfg(feature = "tls")]
+mod tls_unix;
@@ -0,0 +1,3 @@
+#[cfg_attr(unix, path = "os_unix.rs")]
+#[cfg_attr(target_os = "linux", path = "os_linux.rs")]
+mod os;
@@ -0,0 +1,6 @@
+#[cfg_attr(unix, path = "sys/unix.rs")]
+#[cfg_attr(windows, path = "sys/windows.rs")]
+mod sys;
+
+#[cfg_attr(feature = "alt", cfg_attr(unix, path = "alt_unix.rs"))]
+mod alt;
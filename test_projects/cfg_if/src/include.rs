@@ -0,0 +1,16 @@
+cfg_if::cfg_if! {
+    if #[cfg(unix)] {
+        include!("unix_items.rs");
+    } else {
+        include!("other_items.rs");
+    }
+}
+
+fn value() -> i32 {
+    cfg_if::cfg_if! {
+        if #[cfg(unix)] {
+            include!("unix_value.rs");
+        }
+    }
+    0
+}
@@ -0,0 +1,9 @@
+cfg_if::cfg_if! {
+    if #[cfg(unix)] {
+        mod unix;
+    } else if #[cfg(windows)] {
+        mod windows;
+    } else {
+        mod fallback;
+    }
+}
@@ -0,0 +1,5 @@
+cfg_if::cfg_if! {
+    if unix {
+        mod unix;
+    }
+}
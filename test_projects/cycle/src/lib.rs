@@ -0,0 +1,6 @@
+#[path = "lib.rs"]
+mod again;
+
+include!("snippet.rs");
+
+const SELF: &str = include_str!("lib.rs");
@@ -0,0 +1 @@
+include!("snippet.rs");
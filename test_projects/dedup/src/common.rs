@@ -0,0 +1 @@
+const C: &str = include_str!("data.txt");
@@ -0,0 +1,14 @@
+#[path = "common.rs"]
+mod first;
+#[path = "common.rs"]
+mod second;
+
+const A: &str = include_str!("data.txt");
+const B: &str = include_str!("data.txt");
+
+#[cfg(unix)]
+#[path = "shared.rs"]
+mod third;
+#[cfg(windows)]
+#[path = "shared.rs"]
+mod fourth;
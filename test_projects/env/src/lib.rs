@@ -0,0 +1,5 @@
+include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
+
+const README: &str = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/README.md"));
+const VERSION: &str = include_str!(concat!("../", stringify!(VERSION), ".txt"));
+const DATA: &[u8] = include_bytes!(concat!("data", 1, ".bin"));
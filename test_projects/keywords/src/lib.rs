@@ -0,0 +1,10 @@
+mod r#type;
+
+mod r#match {
+    mod r#struct;
+}
+
+#[path = "cafe.rs"]
+mod café;
+
+mod naïve;
@@ -0,0 +1 @@
+mod r#async;
@@ -0,0 +1,17 @@
+lazy_static! {
+    static ref SCHEMA: &'static str = include_str!("schema.sql");
+}
+
+thread_local! {
+    static TABLE: [u8; 4] = *include_bytes!("table.bin");
+}
+
+paste::paste! {
+    mod pasted;
+}
+
+macro_rules! include_snippet {
+    ($path:expr) => {
+        include!($path);
+    };
+}
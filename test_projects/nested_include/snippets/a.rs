@@ -0,0 +1,5 @@
+include!("b/b.rs");
+
+const DATA: &str = include_str!("data.txt");
+
+mod m;
@@ -0,0 +1 @@
+const C: u8 = 0;
@@ -0,0 +1 @@
+include!("../snippets/a.rs");
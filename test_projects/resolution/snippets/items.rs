@@ -0,0 +1,8 @@
+mod plain;
+
+#[path = "p.rs"]
+mod p;
+
+mod i {
+    mod plain;
+}
@@ -0,0 +1,56 @@
+mod plain;
+
+#[path = "p0.rs"]
+mod p0;
+
+mod i1 {
+    mod plain;
+
+    #[path = "p1.rs"]
+    mod p1;
+
+    mod i2 {
+        mod plain;
+
+        #[path = "p2.rs"]
+        mod p2;
+    }
+
+    #[path = "ip2"]
+    mod ip2 {
+        mod plain;
+
+        #[path = "p.rs"]
+        mod p;
+    }
+}
+
+#[path = "ip1"]
+mod ip1 {
+    mod plain;
+
+    #[path = "p.rs"]
+    mod p;
+
+    mod i2 {
+        mod plain;
+
+        #[path = "p.rs"]
+        mod p;
+    }
+}
+
+fn block() {
+    #[path = "in_block.rs"]
+    mod in_block;
+
+    mod inline_in_block {
+        #[path = "p.rs"]
+        mod p;
+    }
+
+    #[path = "ip_in_block"]
+    mod ip_in_block {
+        mod plain;
+    }
+}
@@ -0,0 +1,11 @@
+mod adjacent;
+mod mod_rs;
+
+#[path = "other/path_file.rs"]
+mod path_file;
+
+mod inline {
+    include!("../snippets/items.rs");
+}
+
+include!("../snippets/top.rs");
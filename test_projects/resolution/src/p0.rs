@@ -0,0 +1,5 @@
+mod leaf;
+
+mod inner {
+    mod leaf;
+}
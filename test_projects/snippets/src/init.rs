@@ -0,0 +1,5 @@
+{
+    let a = 1;
+    let _ = include_str!("data.txt");
+    a
+}
@@ -0,0 +1 @@
+mod from_items;
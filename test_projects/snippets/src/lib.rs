@@ -0,0 +1,8 @@
+static TABLE: [u8; 4] = include!("table.rs");
+
+fn init() -> u32 {
+    include!("init.rs");
+    include!("tail.rs")
+}
+
+include!("items.rs");
@@ -0,0 +1,79 @@
+//! Helpers shared by the test crates
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Whether a local rustc can be run
+pub fn rustc_available() -> bool {
+    Command::new("rustc")
+        .arg("--version")
+        .stdout(Stdio::null())
+        .status()
+        .is_ok_and(|x| x.success())
+}
+
+/// Parses the dependencies of the first rule in a Makefile-style `.d` file
+pub fn parse_dep_info(content: &str) -> Vec<PathBuf> {
+    let deps = match content.lines().next().and_then(|x| x.split_once(": ")) {
+        Some((_, deps)) => deps,
+        None => return vec![],
+    };
+
+    let mut paths = vec![];
+    let mut current = String::new();
+    let mut chars = deps.chars();
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\\' => current.extend(chars.next()),
+            ' ' if !current.is_empty() => paths.push(PathBuf::from(std::mem::take(&mut current))),
+            ' ' => {}
+            ch => current.push(ch),
+        }
+    }
+
+    if !current.is_empty() {
+        paths.push(PathBuf::from(current));
+    }
+
+    paths
+}
+
+/// Files the local rustc reads for the crate at `root`, `None` if it fails to compile it
+pub fn rustc_srcfiles(root: &Path, env: &[(String, String)]) -> Option<Vec<PathBuf>> {
+    let dep_info = std::env::temp_dir().join(format!(
+        "srcfiles-{}-{}.d",
+        std::process::id(),
+        root.to_string_lossy().replace(['/', '\\'], "_")
+    ));
+
+    let status = Command::new("rustc")
+        .args([
+            "--edition",
+            "2018",
+            "--crate-type",
+            "lib",
+            "--emit=dep-info",
+            "-o",
+        ])
+        .arg(&dep_info)
+        .arg(root)
+        .envs(env.iter().cloned())
+        .stderr(Stdio::null())
+        .status()
+        .ok()?;
+
+    let content = std::fs::read_to_string(&dep_info).ok();
+    let _ = std::fs::remove_file(&dep_info);
+
+    if !status.success() {
+        return None;
+    }
+
+    Some(
+        parse_dep_info(&content?)
+            .iter()
+            .map(|x| srcfiles::normalize_path(x))
+            .collect(),
+    )
+}
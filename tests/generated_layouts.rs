@@ -0,0 +1,280 @@
+//! Random crate layouts checked against the files they are built from
+
+mod common;
+
+use common::{rustc_available, rustc_srcfiles};
+use srcfiles::{normalize_path, ModPath, ModType, Options};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Linear congruential generator, so that every seed gives the same layout everywhere
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        self.0 >> 33
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+
+    fn chance(&mut self, percent: u64) -> bool {
+        self.below(100) < percent
+    }
+}
+
+/// Where modules declared at some point resolve, tracked the way rustc does it
+#[derive(Clone)]
+struct Scope {
+    /// File the declarations are written in, relative to the crate root
+    file: PathBuf,
+    /// Directory of the module
+    dir: PathBuf,
+    /// File name of a non-mod.rs file, not yet added to `dir`
+    offset: Option<String>,
+    /// Cleared in blocks, where modules without `#[path]` are rejected
+    owned: bool,
+    /// Inside a function body, where `include!` is an expression
+    in_block: bool,
+}
+
+impl Scope {
+    fn file(file: PathBuf) -> Self {
+        Scope {
+            dir: file.parent().unwrap().to_path_buf(),
+            file,
+            offset: None,
+            owned: true,
+            in_block: false,
+        }
+    }
+
+    fn module_dir(&self) -> PathBuf {
+        match &self.offset {
+            Some(offset) => self.dir.join(offset),
+            None => self.dir.clone(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Layout {
+    /// Contents of every file by path relative to the crate root
+    files: Vec<(PathBuf, String)>,
+    /// Files the crate consists of
+    expected: BTreeSet<PathBuf>,
+    names: usize,
+}
+
+impl Layout {
+    fn name(&mut self, prefix: &str) -> String {
+        self.names += 1;
+        format!("{}{}", prefix, self.names)
+    }
+
+    fn add_file(&mut self, path: PathBuf, content: String, expected: bool) {
+        let path = normalize_path(&path);
+        if expected {
+            self.expected.insert(path.clone());
+        }
+        self.files.push((path, content));
+    }
+
+    /// Generates the items of a module, adding the files they need
+    fn items(&mut self, rng: &mut Rng, scope: &Scope, depth: usize, indent: &str) -> String {
+        let mut source = String::new();
+        let count = if depth >= 4 { 0 } else { rng.below(4) };
+
+        for _ in 0..count {
+            let item = match rng.below(6) {
+                0 if scope.owned => self.default_mod(rng, scope, depth),
+                0 | 1 => self.path_mod(rng, scope, depth),
+                2 => self.inline_mod(rng, scope, depth),
+                3 => self.inline_path_mod(rng, scope, depth),
+                // include! in a function body is an expression, which can't hold modules
+                4 if !scope.in_block => self.include(rng, scope, depth),
+                _ => self.block(rng, scope, depth),
+            };
+
+            for line in item.lines() {
+                if !line.is_empty() {
+                    source.push_str(indent);
+                }
+                source.push_str(line);
+                source.push('\n');
+            }
+        }
+
+        source
+    }
+
+    /// `mod name;`, in `name.rs` or `name/mod.rs`
+    fn default_mod(&mut self, rng: &mut Rng, scope: &Scope, depth: usize) -> String {
+        let name = self.name("m");
+        let dir = scope.module_dir();
+
+        let (file, offset) = if rng.chance(50) {
+            (dir.join(format!("{}.rs", name)), Some(name.clone()))
+        } else {
+            (dir.join(&name).join("mod.rs"), None)
+        };
+
+        // Where a resolver ignoring the offset would look
+        if scope.offset.is_some() {
+            self.add_file(scope.dir.join(format!("{}.rs", name)), String::new(), false);
+        }
+
+        let mut child = Scope::file(file.clone());
+        child.offset = offset;
+        let content = self.items(rng, &child, depth + 1, "");
+        self.add_file(file, content, true);
+
+        format!("mod {};\n", name)
+    }
+
+    /// `#[path = "..."] mod name;`, relative to the directory without the offset
+    fn path_mod(&mut self, rng: &mut Rng, scope: &Scope, depth: usize) -> String {
+        let name = self.name("p");
+        // `..` only goes back out of a directory that exists, the file itself is in it
+        let dir_name = scope.dir.file_name().unwrap().to_str().unwrap();
+        let relative = match rng.below(3) {
+            0 => format!("{}.rs", name),
+            1 => format!("{}_dir/{}.rs", name, name),
+            _ => format!("../{}/{}_file.rs", dir_name, name),
+        };
+        let file = scope.dir.join(&relative);
+
+        if scope.offset.is_some() {
+            self.add_file(scope.module_dir().join(&relative), String::new(), false);
+        }
+
+        let content = self.items(rng, &Scope::file(file.clone()), depth + 1, "");
+        self.add_file(file, content, true);
+
+        format!("#[path = \"{}\"]\nmod {};\n", relative, name)
+    }
+
+    /// `mod name { ... }`, taking the offset into its directory
+    fn inline_mod(&mut self, rng: &mut Rng, scope: &Scope, depth: usize) -> String {
+        let name = self.name("i");
+        let child = Scope {
+            file: scope.file.clone(),
+            dir: scope.module_dir().join(&name),
+            offset: None,
+            owned: scope.owned,
+            in_block: scope.in_block,
+        };
+        let body = self.items(rng, &child, depth + 1, "    ");
+
+        format!("mod {} {{\n{}}}\n", name, body)
+    }
+
+    /// `#[path = "dir"] mod name { ... }`, a directory relative to the one without the offset
+    fn inline_path_mod(&mut self, rng: &mut Rng, scope: &Scope, depth: usize) -> String {
+        let name = self.name("d");
+        let relative = format!("{}_dir", name);
+        let child = Scope {
+            file: scope.file.clone(),
+            dir: scope.dir.join(&relative),
+            offset: None,
+            owned: true,
+            in_block: scope.in_block,
+        };
+        let body = self.items(rng, &child, depth + 1, "    ");
+
+        format!("#[path = \"{}\"]\nmod {} {{\n{}}}\n", relative, name, body)
+    }
+
+    /// `include!("...")` of a file resolving modules relative to itself
+    fn include(&mut self, rng: &mut Rng, scope: &Scope, depth: usize) -> String {
+        let name = self.name("inc");
+        let relative = if rng.chance(50) {
+            format!("{}.rs", name)
+        } else {
+            format!("{}/snippet.rs", name)
+        };
+        let file = scope.file.parent().unwrap().join(&relative);
+
+        let content = self.items(rng, &Scope::file(file.clone()), depth + 1, "");
+        self.add_file(file, content, true);
+
+        format!("include!(\"{}\");\n", relative)
+    }
+
+    /// `fn name() { ... }`, where the offset is dropped
+    fn block(&mut self, rng: &mut Rng, scope: &Scope, depth: usize) -> String {
+        let name = self.name("f");
+        let child = Scope {
+            file: scope.file.clone(),
+            dir: scope.dir.clone(),
+            offset: None,
+            owned: false,
+            in_block: true,
+        };
+        let body = self.items(rng, &child, depth + 1, "    ");
+
+        format!("fn {}() {{\n{}}}\n", name, body)
+    }
+
+    fn generate(seed: u64) -> Self {
+        let mut rng = Rng(seed);
+        let mut layout = Layout::default();
+        let root = PathBuf::from("src/lib.rs");
+
+        let content = layout.items(&mut rng, &Scope::file(root.clone()), 0, "");
+        layout.add_file(root, content, true);
+
+        layout
+    }
+
+    fn write(&self, dir: &Path) {
+        for (path, content) in &self.files {
+            let path = dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+    }
+}
+
+#[test]
+fn generated_layouts_test() {
+    let base = std::env::temp_dir().join(format!("srcfiles-layouts-{}", std::process::id()));
+    let rustc = rustc_available();
+
+    for seed in 0..200 {
+        let layout = Layout::generate(seed);
+        let dir = base.join(seed.to_string());
+        layout.write(&dir);
+
+        let found = srcfiles::mod_srcfiles(
+            ModPath::new(dir.join("src/lib.rs"), ModType::ModRs),
+            &Options::default(),
+        )
+        .unwrap_or_else(|result| panic!("seed {}: {}", seed, result));
+        let found: BTreeSet<_> = found
+            .into_iter()
+            .map(|x| x.path.strip_prefix(&dir).unwrap().to_path_buf())
+            .collect();
+        assert_eq!(found, layout.expected, "seed {}", seed);
+
+        // Every few layouts are also compiled, if rustc is around
+        if rustc && seed % 10 == 0 {
+            let rustc_files: BTreeSet<_> = rustc_srcfiles(&dir.join("src/lib.rs"), &[])
+                .unwrap_or_else(|| panic!("rustc rejects seed {}", seed))
+                .into_iter()
+                .map(|x| x.strip_prefix(&dir).unwrap().to_path_buf())
+                .collect();
+            assert_eq!(rustc_files, layout.expected, "seed {}", seed);
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    let _ = fs::remove_dir_all(&base);
+}
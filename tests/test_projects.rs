@@ -1,7 +1,12 @@
-use srcfiles;
+mod common;
 
-use srcfiles::{error::Error, SourceFileDesc};
-use std::path::PathBuf;
+use common::{rustc_available, rustc_srcfiles};
+use srcfiles::{
+    error::Error, CfgSet, Diagnostic, ModType, Options, PathStyle, Severity, SnippetContext,
+    SourceFileDesc, SourceFileType, SourceOrder,
+};
+use std::path::{Path, PathBuf};
+use std::process::Command;
 
 fn assert_has_source(srcfiles: &[SourceFileDesc], path: &str) {
     assert!(
@@ -9,7 +14,19 @@ fn assert_has_source(srcfiles: &[SourceFileDesc], path: &str) {
             .iter()
             .any(|desc| desc.path.canonicalize().unwrap()
                 == PathBuf::from(path).canonicalize().unwrap()),
-        format!("No source with path {}", path)
+        "No source with path {}",
+        path
+    );
+}
+
+fn assert_source_cfg(srcfiles: &[SourceFileDesc], path: &str, cfg: &str) {
+    let desc = srcfiles
+        .iter()
+        .find(|desc| desc.path == Path::new(path))
+        .unwrap_or_else(|| panic!("No source with path {}", path));
+    assert_eq!(
+        desc.cfg.as_ref().map(ToString::to_string).as_deref(),
+        Some(cfg)
     );
 }
 
@@ -23,15 +40,19 @@ fn assert_missing_files(errors: &[(SourceFileDesc, Error)], path: &str) {
             } else {
                 None
             })
-            .any(|desc| desc.path == PathBuf::from(path)),
-        format!("No missing file with path {}", path)
+            .any(|desc| desc.path == Path::new(path)),
+        "No missing file with path {}",
+        path
     );
 }
 
 #[test]
 fn simple_test() {
-    let result =
-        srcfiles::crate_srcfiles(PathBuf::from("test_projects/simple/src/main.rs")).unwrap_err();
+    let result = srcfiles::crate_srcfiles(
+        PathBuf::from("test_projects/simple/src/main.rs"),
+        &Options::default(),
+    )
+    .unwrap_err();
     let (srcfiles, errors) = (result.get_sources(), result.into_errors());
     assert_eq!(srcfiles.len(), 7);
     assert_has_source(&srcfiles, "test_projects/simple/src/main.rs");
@@ -51,8 +72,11 @@ fn simple_test() {
 
 #[test]
 fn path_attr_test() {
-    let result =
-        srcfiles::crate_srcfiles(PathBuf::from("test_projects/paths/src/main.rs")).unwrap_err();
+    let result = srcfiles::crate_srcfiles(
+        PathBuf::from("test_projects/paths/src/main.rs"),
+        &Options::default(),
+    )
+    .unwrap_err();
     let (srcfiles, errors) = (result.get_sources(), result.into_errors());
 
     assert_eq!(srcfiles.len(), 7);
@@ -64,15 +88,18 @@ fn path_attr_test() {
     assert_has_source(&srcfiles, "test_projects/paths/g/mod.rs");
     assert_has_source(&srcfiles, "test_projects/paths/g/actual_mod.rs");
     assert_eq!(errors.len(), 3);
-    assert_missing_files(&errors, "test_projects/paths/src/../src/b/c.rs");
-    assert_missing_files(&errors, "test_projects/paths/src/../src/b/c/mod.rs");
-    assert_missing_files(&errors, "test_projects/paths/src/../g/../src/f.rs");
+    assert_missing_files(&errors, "test_projects/paths/src/b/c.rs");
+    assert_missing_files(&errors, "test_projects/paths/src/b/c/mod.rs");
+    assert_missing_files(&errors, "test_projects/paths/src/f.rs");
 }
 
 #[test]
 fn inline_mods_test() {
-    let srcfiles =
-        srcfiles::crate_srcfiles(PathBuf::from("test_projects/inline/src/lib.rs")).unwrap();
+    let srcfiles = srcfiles::crate_srcfiles(
+        PathBuf::from("test_projects/inline/src/lib.rs"),
+        &Options::default(),
+    )
+    .unwrap();
     assert_eq!(srcfiles.len(), 5);
     assert_has_source(&srcfiles, "test_projects/inline/src/lib.rs");
     assert_has_source(&srcfiles, "test_projects/inline/g/mod.rs");
@@ -80,3 +107,1224 @@ fn inline_mods_test() {
     assert_has_source(&srcfiles, "test_projects/inline/src/a/c/d/mod.rs");
     assert_has_source(&srcfiles, "test_projects/inline/src/a/c/e/e/e.rs");
 }
+
+#[test]
+fn cfg_test() {
+    let srcfiles = srcfiles::crate_srcfiles(
+        PathBuf::from("test_projects/cfg/src/lib.rs"),
+        &Options::default(),
+    )
+    .unwrap();
+    assert_eq!(srcfiles.len(), 7);
+    assert_source_cfg(&srcfiles, "test_projects/cfg/src/unix.rs", "unix");
+    assert_source_cfg(
+        &srcfiles,
+        "test_projects/cfg/src/unix/tls_unix.rs",
+        r#"all(unix, feature = "tls")"#,
+    );
+    assert_source_cfg(
+        &srcfiles,
+        "test_projects/cfg/src/tls.rs",
+        r#"all(feature = "tls", not(target_os = "macos"))"#,
+    );
+    assert_source_cfg(
+        &srcfiles,
+        "test_projects/cfg/src/testing/helpers.rs",
+        r#"any(test, feature = "bench")"#,
+    );
+    assert_source_cfg(&srcfiles, "test_projects/cfg/src/data.txt", "not(unix)");
+
+    let mut cfg_set = CfgSet::new();
+    cfg_set.insert_flag("unix");
+    cfg_set.insert_key_value("feature", "tls");
+    let options = Options {
+        cfg_set: Some(cfg_set),
+        ..Options::default()
+    };
+
+    let srcfiles =
+        srcfiles::crate_srcfiles(PathBuf::from("test_projects/cfg/src/lib.rs"), &options).unwrap();
+    assert_eq!(srcfiles.len(), 4);
+    assert_has_source(&srcfiles, "test_projects/cfg/src/lib.rs");
+    assert_has_source(&srcfiles, "test_projects/cfg/src/unix.rs");
+    assert_has_source(&srcfiles, "test_projects/cfg/src/unix/tls_unix.rs");
+    assert_has_source(&srcfiles, "test_projects/cfg/src/tls.rs");
+}
+
+#[test]
+fn cfg_if_test() {
+    let srcfiles = srcfiles::crate_srcfiles(
+        PathBuf::from("test_projects/cfg_if/src/lib.rs"),
+        &Options::default(),
+    )
+    .unwrap();
+    assert_eq!(srcfiles.len(), 4);
+    assert_source_cfg(&srcfiles, "test_projects/cfg_if/src/unix.rs", "unix");
+    assert_source_cfg(
+        &srcfiles,
+        "test_projects/cfg_if/src/windows.rs",
+        "all(not(unix), windows)",
+    );
+    assert_source_cfg(
+        &srcfiles,
+        "test_projects/cfg_if/src/fallback.rs",
+        "all(not(unix), not(windows))",
+    );
+
+    let mut cfg_set = CfgSet::new();
+    cfg_set.insert_flag("unix");
+    cfg_set.insert_flag("windows");
+    let options = Options {
+        cfg_set: Some(cfg_set),
+        ..Options::default()
+    };
+    let srcfiles =
+        srcfiles::crate_srcfiles(PathBuf::from("test_projects/cfg_if/src/lib.rs"), &options)
+            .unwrap();
+    assert_eq!(srcfiles.len(), 2);
+    assert_has_source(&srcfiles, "test_projects/cfg_if/src/unix.rs");
+
+    let options = Options {
+        cfg_set: Some(CfgSet::new()),
+        ..Options::default()
+    };
+    let srcfiles =
+        srcfiles::crate_srcfiles(PathBuf::from("test_projects/cfg_if/src/lib.rs"), &options)
+            .unwrap();
+    assert_eq!(srcfiles.len(), 2);
+    assert_has_source(&srcfiles, "test_projects/cfg_if/src/fallback.rs");
+
+    let errors = srcfiles::crate_srcfiles(
+        PathBuf::from("test_projects/cfg_if/src/malformed.rs"),
+        &Options::default(),
+    )
+    .unwrap_err()
+    .into_errors();
+    assert_eq!(errors.len(), 1);
+    assert!(matches!(errors[0].1, Error::MalformedCfgIf(..)));
+
+    // Branches expand where the macro is invoked, as items at module level
+    let srcfiles = srcfiles::crate_srcfiles(
+        PathBuf::from("test_projects/cfg_if/src/include.rs"),
+        &Options::default(),
+    )
+    .unwrap();
+    assert_eq!(srcfiles.len(), 4);
+    assert_source_cfg(&srcfiles, "test_projects/cfg_if/src/unix_items.rs", "unix");
+    assert_source_cfg(
+        &srcfiles,
+        "test_projects/cfg_if/src/other_items.rs",
+        "not(unix)",
+    );
+    assert_source_cfg(&srcfiles, "test_projects/cfg_if/src/unix_value.rs", "unix");
+}
+
+#[test]
+fn cfg_attr_path_test() {
+    let result = srcfiles::crate_srcfiles(
+        PathBuf::from("test_projects/cfg_attr/src/lib.rs"),
+        &Options::default(),
+    );
+    // Without a cfg set, the fallback of `sys` is only followed if sys.rs or sys/mod.rs exists
+    let srcfiles = result.unwrap();
+    assert_eq!(srcfiles.len(), 5);
+    assert_source_cfg(&srcfiles, "test_projects/cfg_attr/src/sys/unix.rs", "unix");
+    assert_source_cfg(
+        &srcfiles,
+        "test_projects/cfg_attr/src/sys/windows.rs",
+        "all(not(unix), windows)",
+    );
+    assert_source_cfg(
+        &srcfiles,
+        "test_projects/cfg_attr/src/alt_unix.rs",
+        r#"all(feature = "alt", unix)"#,
+    );
+    assert_source_cfg(
+        &srcfiles,
+        "test_projects/cfg_attr/src/alt.rs",
+        r#"not(all(feature = "alt", unix))"#,
+    );
+
+    // With a cfg set enabling neither path, the fallback is compiled and has to exist
+    let options = Options {
+        cfg_set: Some(CfgSet::new()),
+        ..Options::default()
+    };
+    let errors =
+        srcfiles::crate_srcfiles(PathBuf::from("test_projects/cfg_attr/src/lib.rs"), &options)
+            .unwrap_err()
+            .into_errors();
+    assert_eq!(errors.len(), 2);
+    assert_missing_files(&errors, "test_projects/cfg_attr/src/sys.rs");
+    assert_missing_files(&errors, "test_projects/cfg_attr/src/sys/mod.rs");
+
+    let mut cfg_set = CfgSet::new();
+    cfg_set.insert_flag("unix");
+    let options = Options {
+        cfg_set: Some(cfg_set),
+        ..Options::default()
+    };
+    let srcfiles =
+        srcfiles::crate_srcfiles(PathBuf::from("test_projects/cfg_attr/src/lib.rs"), &options)
+            .unwrap();
+    assert_eq!(srcfiles.len(), 3);
+    assert_has_source(&srcfiles, "test_projects/cfg_attr/src/sys/unix.rs");
+    assert_has_source(&srcfiles, "test_projects/cfg_attr/src/alt.rs");
+
+    let mut cfg_set = CfgSet::new();
+    cfg_set.insert_flag("unix");
+    cfg_set.insert_key_value("feature", "alt");
+    let options = Options {
+        cfg_set: Some(cfg_set),
+        ..Options::default()
+    };
+    let srcfiles =
+        srcfiles::crate_srcfiles(PathBuf::from("test_projects/cfg_attr/src/lib.rs"), &options)
+            .unwrap();
+    assert_eq!(srcfiles.len(), 3);
+    assert_has_source(&srcfiles, "test_projects/cfg_attr/src/sys/unix.rs");
+    assert_has_source(&srcfiles, "test_projects/cfg_attr/src/alt_unix.rs");
+
+    // Like rustc, the first active path is used
+    let srcfiles = srcfiles::crate_srcfiles(
+        PathBuf::from("test_projects/cfg_attr/src/first.rs"),
+        &Options::default(),
+    )
+    .unwrap();
+    assert_eq!(srcfiles.len(), 3);
+    assert_source_cfg(&srcfiles, "test_projects/cfg_attr/src/os_unix.rs", "unix");
+    assert_source_cfg(
+        &srcfiles,
+        "test_projects/cfg_attr/src/os_linux.rs",
+        r#"all(not(unix), target_os = "linux")"#,
+    );
+
+    let mut cfg_set = CfgSet::new();
+    cfg_set.insert_flag("unix");
+    cfg_set.insert_key_value("target_os", "linux");
+    let options = Options {
+        cfg_set: Some(cfg_set),
+        ..Options::default()
+    };
+    let srcfiles = srcfiles::crate_srcfiles(
+        PathBuf::from("test_projects/cfg_attr/src/first.rs"),
+        &options,
+    )
+    .unwrap();
+    assert_eq!(srcfiles.len(), 2);
+    assert_has_source(&srcfiles, "test_projects/cfg_attr/src/os_unix.rs");
+
+    // A malformed predicate is reported once, not again while looking for paths
+    let source = SourceFileDesc::new(
+        PathBuf::from("test_projects/cfg_attr/src/lib.rs"),
+        SourceFileType::RustSource(ModType::ModRs),
+    );
+    let content = "#[cfg_attr(not_a_pred(), path = \"b.rs\")] mod m;";
+    let (_, errors) = srcfiles::process_source_text(&source, content, &Options::default()).unwrap();
+    let invalid_cfgs = errors
+        .iter()
+        .filter(|x| matches!(x, Error::InvalidCfg(..)))
+        .count();
+    assert_eq!(invalid_cfgs, 1, "{:?}", errors);
+}
+
+#[test]
+fn attr_includes_test() {
+    let srcfiles = srcfiles::crate_srcfiles(
+        PathBuf::from("test_projects/attrs/src/lib.rs"),
+        &Options::default(),
+    )
+    .unwrap();
+    assert_eq!(srcfiles.len(), 6);
+    assert_has_source(&srcfiles, "test_projects/attrs/README.md");
+    assert_has_source(&srcfiles, "test_projects/attrs/src/documented.md");
+    assert_has_source(&srcfiles, "test_projects/attrs/src/documented.rs");
+    assert_source_cfg(&srcfiles, "test_projects/attrs/docs/extra.md", "docsrs");
+    assert_source_cfg(
+        &srcfiles,
+        "test_projects/attrs/src/inner.md",
+        r#"all(feature = "x", docsrs)"#,
+    );
+    assert!(srcfiles
+        .iter()
+        .filter(|desc| desc.path.extension().unwrap() == "md")
+        .all(|desc| matches!(desc.file_type, SourceFileType::String)));
+
+    let options = Options {
+        cfg_set: Some(CfgSet::new()),
+        ..Options::default()
+    };
+    let srcfiles =
+        srcfiles::crate_srcfiles(PathBuf::from("test_projects/attrs/src/lib.rs"), &options)
+            .unwrap();
+    assert_eq!(srcfiles.len(), 4);
+}
+
+#[test]
+fn unknown_macros_test() {
+    let srcfiles = srcfiles::crate_srcfiles(
+        PathBuf::from("test_projects/macros/src/lib.rs"),
+        &Options::default(),
+    )
+    .unwrap();
+    assert_eq!(srcfiles.len(), 1);
+
+    let options = Options {
+        scan_unknown_macros: true,
+        ..Options::default()
+    };
+    let srcfiles =
+        srcfiles::crate_srcfiles(PathBuf::from("test_projects/macros/src/lib.rs"), &options)
+            .unwrap();
+    assert_eq!(srcfiles.len(), 5);
+    assert_has_source(&srcfiles, "test_projects/macros/src/schema.sql");
+    assert_has_source(&srcfiles, "test_projects/macros/src/table.bin");
+    assert_has_source(&srcfiles, "test_projects/macros/src/pasted.rs");
+    assert_has_source(&srcfiles, "test_projects/macros/src/pasted/inner.rs");
+    assert!(srcfiles
+        .iter()
+        .all(|desc| desc.heuristic != desc.path.ends_with("lib.rs")));
+}
+
+#[test]
+fn include_env_test() {
+    let errors = srcfiles::crate_srcfiles(
+        PathBuf::from("test_projects/env/src/lib.rs"),
+        &Options::default(),
+    )
+    .unwrap_err()
+    .into_errors();
+    assert_eq!(errors.len(), 1);
+    assert!(matches!(errors[0].1, Error::UnresolvedIncludeArg(..)));
+
+    let mut options = Options::default();
+    options.env.insert(
+        "OUT_DIR".to_owned(),
+        std::env::current_dir()
+            .unwrap()
+            .join("test_projects/env/out")
+            .to_string_lossy()
+            .into_owned(),
+    );
+    let srcfiles =
+        srcfiles::crate_srcfiles(PathBuf::from("test_projects/env/src/lib.rs"), &options).unwrap();
+    assert_eq!(srcfiles.len(), 5);
+    assert_has_source(&srcfiles, "test_projects/env/out/bindings.rs");
+    assert_has_source(&srcfiles, "test_projects/env/README.md");
+    assert_has_source(&srcfiles, "test_projects/env/VERSION.txt");
+    assert_has_source(&srcfiles, "test_projects/env/src/data1.bin");
+}
+
+#[test]
+fn out_dir_test() {
+    let build_dir = Path::new("test_projects/build_script/fake_target/debug/build");
+    let set_modified = |dir: &str, secs: u64| {
+        std::fs::File::open(build_dir.join(dir).join("out"))
+            .unwrap()
+            .set_modified(std::time::UNIX_EPOCH + std::time::Duration::from_secs(secs))
+            .unwrap();
+    };
+    set_modified("build_script-0123456789abcdef", 2_000_000_000);
+    set_modified("build_script-fedcba9876543210", 1_000_000_000);
+    set_modified("other-0123456789abcdef", 3_000_000_000);
+
+    let options = Options {
+        target_dir: Some(PathBuf::from("test_projects/build_script/fake_target")),
+        ..Options::default()
+    };
+    let srcfiles = srcfiles::crate_srcfiles(
+        PathBuf::from("test_projects/build_script/src/lib.rs"),
+        &options,
+    )
+    .unwrap();
+    assert_eq!(srcfiles.len(), 2);
+
+    let generated = srcfiles.iter().find(|desc| desc.build_output).unwrap();
+    assert!(generated
+        .path
+        .ends_with(build_dir.join("build_script-0123456789abcdef/out/generated.rs")));
+    assert!(srcfiles.iter().any(|desc| !desc.build_output));
+
+    // Paths with `..`, and OUT_DIR given relative to the current directory
+    let options = Options {
+        target_dir: Some(PathBuf::from(
+            "test_projects/env/../build_script/fake_target",
+        )),
+        ..Options::default()
+    };
+    let relative_out_dir =
+        build_dir.join("build_script-fedcba9876543210/../build_script-0123456789abcdef/out");
+    let mut env_options = Options::default();
+    env_options.env.insert(
+        "OUT_DIR".to_owned(),
+        relative_out_dir.to_string_lossy().into_owned(),
+    );
+
+    for options in [options, env_options] {
+        let srcfiles = srcfiles::crate_srcfiles(
+            PathBuf::from("test_projects/build_script/src/lib.rs"),
+            &options,
+        )
+        .unwrap();
+        let generated = srcfiles.iter().find(|desc| desc.build_output).unwrap();
+        assert!(generated
+            .path
+            .ends_with("build_script-0123456789abcdef/out/generated.rs"));
+    }
+}
+
+#[test]
+fn nested_include_test() {
+    let srcfiles = srcfiles::crate_srcfiles(
+        PathBuf::from("test_projects/nested_include/src/lib.rs"),
+        &Options::default(),
+    )
+    .unwrap();
+    assert_eq!(srcfiles.len(), 6);
+    assert_has_source(&srcfiles, "test_projects/nested_include/snippets/a.rs");
+    assert_has_source(&srcfiles, "test_projects/nested_include/snippets/b/b.rs");
+    assert_has_source(&srcfiles, "test_projects/nested_include/snippets/c.rs");
+    assert_has_source(&srcfiles, "test_projects/nested_include/snippets/data.txt");
+    // Like in rustc, modules declared in snippets resolve relative to the snippet, as in a mod.rs
+    assert_has_source(&srcfiles, "test_projects/nested_include/snippets/m.rs");
+}
+
+#[test]
+fn snippet_context_test() {
+    let srcfiles = srcfiles::crate_srcfiles(
+        PathBuf::from("test_projects/snippets/src/lib.rs"),
+        &Options::default(),
+    )
+    .unwrap();
+    assert_eq!(srcfiles.len(), 7);
+    assert_has_source(&srcfiles, "test_projects/snippets/src/data.txt");
+    assert_has_source(&srcfiles, "test_projects/snippets/src/from_items.rs");
+
+    let context = |path: &str| {
+        srcfiles
+            .iter()
+            .find_map(|desc| match &desc.file_type {
+                SourceFileType::RustSnippet(_, context) if desc.path.ends_with(path) => {
+                    Some(*context)
+                }
+                _ => None,
+            })
+            .unwrap()
+    };
+    assert_eq!(context("table.rs"), SnippetContext::Expr);
+    assert_eq!(context("init.rs"), SnippetContext::Stmts);
+    assert_eq!(context("tail.rs"), SnippetContext::Stmts);
+    assert_eq!(context("items.rs"), SnippetContext::Items);
+
+    // Like rustc, statements in a snippet included in statement position are rejected
+    let init = srcfiles
+        .iter()
+        .find(|desc| desc.path.ends_with("init.rs"))
+        .unwrap();
+    let result = srcfiles::process_source_text(init, "let a = 1;", &Options::default());
+    assert!(matches!(result, Err(Error::Syn(..))));
+}
+
+#[test]
+fn cycle_test() {
+    let result = srcfiles::crate_srcfiles(
+        PathBuf::from("test_projects/cycle/src/lib.rs"),
+        &Options::default(),
+    )
+    .unwrap_err();
+
+    // Edges closing a cycle are not recorded as inclusions
+    let lib = &result.sources[0].0;
+    assert!(lib.path.ends_with("lib.rs"));
+    let declarations: Vec<_> = lib.parents.iter().map(|x| &x.declaration).collect();
+    assert_eq!(declarations, ["include_str!(\"lib.rs\")"]);
+    assert!(lib.other_module_paths.is_empty());
+
+    let errors = result.into_errors();
+    let mut cycles: Vec<_> = errors
+        .iter()
+        .map(|(_, error)| match error {
+            Error::Cycle(chain, _) => chain
+                .iter()
+                .map(|x| x.file_name().unwrap().to_str().unwrap())
+                .collect::<Vec<_>>(),
+            error => panic!("Unexpected error {}", error),
+        })
+        .collect();
+    cycles.sort();
+
+    // include_str! of the file itself is not a cycle
+    assert_eq!(
+        cycles,
+        vec![vec!["lib.rs", "lib.rs"], vec!["snippet.rs", "snippet.rs"]]
+    );
+}
+
+#[test]
+fn dedup_test() {
+    let srcfiles = srcfiles::crate_srcfiles(
+        PathBuf::from("test_projects/dedup/src/lib.rs"),
+        &Options::default(),
+    )
+    .unwrap();
+    assert_eq!(srcfiles.len(), 5);
+
+    let declarations = |path: &str| {
+        let desc = srcfiles
+            .iter()
+            .find(|desc| desc.path.ends_with(path))
+            .unwrap();
+        let mut declarations: Vec<_> = desc
+            .parents
+            .iter()
+            .map(|x| {
+                format!(
+                    "{}: {}",
+                    x.parent_file.file_name().unwrap().to_str().unwrap(),
+                    x.declaration
+                )
+            })
+            .collect();
+        declarations.sort();
+        declarations
+    };
+
+    assert!(declarations("lib.rs").is_empty());
+    assert_eq!(
+        declarations("common.rs"),
+        vec!["lib.rs: mod first;", "lib.rs: mod second;"]
+    );
+    assert_eq!(
+        declarations("data.txt"),
+        vec![
+            r#"common.rs: include_str!("data.txt")"#,
+            r#"lib.rs: include_str!("data.txt")"#,
+            r#"lib.rs: include_str!("data.txt")"#,
+        ]
+    );
+
+    // Files below a file reached by two routes are reached by both
+    let find = |path: &str| srcfiles.iter().find(|x| x.path.ends_with(path)).unwrap();
+    let module_paths = |path: &str| {
+        find(path)
+            .module_paths()
+            .map(|x| x.join("::"))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(module_paths("shared.rs"), ["crate::third", "crate::fourth"]);
+    assert_eq!(
+        module_paths("x.rs"),
+        ["crate::third::x", "crate::fourth::x"]
+    );
+    assert_eq!(
+        find("x.rs").cfg.as_ref().map(ToString::to_string),
+        Some("any(unix, windows)".to_owned())
+    );
+
+    let tree = srcfiles::crate_module_tree(
+        PathBuf::from("test_projects/dedup/src/lib.rs"),
+        &Options::default(),
+    )
+    .unwrap();
+    let mut modules: Vec<_> = tree.nodes.iter().map(|x| x.path.join("::")).collect();
+    modules.sort();
+    assert_eq!(
+        modules,
+        [
+            "crate",
+            "crate::first",
+            "crate::fourth",
+            "crate::fourth::x",
+            "crate::second",
+            "crate::third",
+            "crate::third::x",
+        ]
+    );
+}
+
+#[test]
+fn order_test() {
+    let paths = |order| {
+        let options = Options {
+            order,
+            ..Options::default()
+        };
+        srcfiles::crate_srcfiles(PathBuf::from("test_projects/simple/src/main.rs"), &options)
+            .unwrap_err()
+            .get_sources()
+            .into_iter()
+            .map(|desc| {
+                desc.path
+                    .strip_prefix("test_projects/simple/src")
+                    .unwrap()
+                    .to_str()
+                    .unwrap()
+                    .to_owned()
+            })
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        paths(SourceOrder::ModuleTree),
+        vec![
+            "main.rs",
+            "a.rs",
+            "a/c.rs",
+            "a/d/mod.rs",
+            "b/mod.rs",
+            "b/f/mod.rs",
+            "b/g.rs",
+        ]
+    );
+    assert_eq!(
+        paths(SourceOrder::Path),
+        vec![
+            "a/c.rs",
+            "a/d/mod.rs",
+            "a.rs",
+            "b/f/mod.rs",
+            "b/g.rs",
+            "b/mod.rs",
+            "main.rs",
+        ]
+    );
+}
+
+#[test]
+fn path_style_test() {
+    let paths = |path_style| {
+        let options = Options {
+            path_style,
+            ..Options::default()
+        };
+        let mut paths: Vec<_> =
+            srcfiles::crate_srcfiles(PathBuf::from("test_projects/./paths/src/main.rs"), &options)
+                .unwrap_err()
+                .get_sources()
+                .into_iter()
+                .map(|desc| desc.path)
+                .collect();
+        paths.sort();
+        paths
+    };
+
+    let normalized = paths(PathStyle::Normalized);
+    assert!(normalized
+        .iter()
+        .all(|path| path.starts_with("test_projects/paths") && !path.ends_with("..")));
+    assert!(normalized.contains(&PathBuf::from("test_projects/paths/g/mod.rs")));
+
+    let relative = paths(PathStyle::RelativeToCrateRoot);
+    assert_eq!(relative[0], PathBuf::from("g/actual_mod.rs"));
+    assert!(relative.contains(&PathBuf::from("src/main.rs")));
+
+    let relative = paths(PathStyle::RelativeTo(PathBuf::from(
+        "test_projects/paths/g",
+    )));
+    assert_eq!(relative[0], PathBuf::from("../src/a.rs"));
+    assert!(relative.contains(&PathBuf::from("mod.rs")));
+
+    let absolute = paths(PathStyle::Absolute);
+    let current_dir = std::env::current_dir().unwrap();
+    assert!(absolute.contains(&current_dir.join("test_projects/paths/src/d.rs")));
+}
+
+#[test]
+fn module_tree_test() {
+    let tree = srcfiles::crate_module_tree(
+        PathBuf::from("test_projects/inline/src/lib.rs"),
+        &Options::default(),
+    )
+    .unwrap();
+    let paths: Vec<_> = tree.nodes.iter().map(|x| x.path.join("::")).collect();
+    assert_eq!(
+        paths,
+        [
+            "crate",
+            "crate::a",
+            "crate::a::b",
+            "crate::a::b::d",
+            "crate::a::b::e",
+            "crate::a::b::e::e",
+            "crate::a::b::e::e::g",
+            "crate::a::b::e::e::g::f",
+            "crate::a::b::e::e::g::f::g",
+            "crate::a::b::e::e::g::f::g::h",
+        ]
+    );
+
+    let b = &tree.nodes[2];
+    assert_eq!(b.mod_type, None);
+    assert_eq!(b.file, Path::new("test_projects/inline/src/lib.rs"));
+    assert_eq!(b.children, [3, 4]);
+    let declaration = b.declaration.as_ref().unwrap();
+    assert_eq!((declaration.line, declaration.column), (3, 5));
+
+    let d = &tree.nodes[3];
+    assert_eq!(d.mod_type, Some(ModType::ModRs));
+    assert_eq!(d.file, Path::new("test_projects/inline/src/a/c/d/mod.rs"));
+    assert_eq!(d.parent, Some(2));
+
+    let h = &tree.nodes[9];
+    assert_eq!(h.file, Path::new("test_projects/inline/g/h.rs"));
+    assert_eq!(
+        h.declaration.as_ref().unwrap().to_string(),
+        "test_projects/inline/g/mod.rs:1:1"
+    );
+
+    let tree = srcfiles::crate_module_tree(
+        PathBuf::from("test_projects/nested_include/src/lib.rs"),
+        &Options::default(),
+    )
+    .unwrap();
+    assert_eq!(tree.nodes.len(), 2);
+    let includes: Vec<_> = tree.nodes[0]
+        .includes
+        .iter()
+        .map(|x| x.declaration.as_str())
+        .collect();
+    assert_eq!(
+        includes,
+        [
+            "include!(\"../snippets/a.rs\")",
+            "include!(\"b/b.rs\")",
+            "include_str!(\"data.txt\")",
+            "include!(\"../c.rs\")",
+        ]
+    );
+    assert_eq!(tree.nodes[1].path, ["crate", "m"]);
+    assert_eq!(tree.nodes[0].children, [1]);
+}
+
+#[test]
+fn module_path_test() {
+    let srcfiles = srcfiles::crate_srcfiles(
+        PathBuf::from("test_projects/inline/src/lib.rs"),
+        &Options::default(),
+    )
+    .unwrap();
+    let module_path = |path: &str| {
+        srcfiles
+            .iter()
+            .find(|x| x.path == Path::new(path))
+            .unwrap()
+            .module_path
+            .join("::")
+    };
+    assert_eq!(module_path("test_projects/inline/src/lib.rs"), "crate");
+    assert_eq!(
+        module_path("test_projects/inline/src/a/c/d/mod.rs"),
+        "crate::a::b::d"
+    );
+    assert_eq!(
+        module_path("test_projects/inline/src/a/c/e/e/e.rs"),
+        "crate::a::b::e::e"
+    );
+    assert_eq!(
+        module_path("test_projects/inline/g/h.rs"),
+        "crate::a::b::e::e::g::f::g::h"
+    );
+
+    let srcfiles = srcfiles::crate_srcfiles(
+        PathBuf::from("test_projects/nested_include/src/lib.rs"),
+        &Options::default(),
+    )
+    .unwrap();
+    for desc in &srcfiles {
+        let expected = if desc.path.ends_with("m.rs") {
+            vec!["crate", "m"]
+        } else {
+            vec!["crate"]
+        };
+        assert_eq!(desc.module_path, expected);
+    }
+}
+
+#[test]
+fn location_test() {
+    let errors = srcfiles::crate_srcfiles(
+        PathBuf::from("test_projects/env/src/lib.rs"),
+        &Options::default(),
+    )
+    .unwrap_err()
+    .into_errors();
+    assert_eq!(
+        errors[0].1.location().unwrap().to_string(),
+        "test_projects/env/src/lib.rs:1:1"
+    );
+    assert!(errors[0]
+        .1
+        .to_string()
+        .starts_with("test_projects/env/src/lib.rs:1:1: Unresolved include argument"));
+
+    let errors = srcfiles::crate_srcfiles(
+        PathBuf::from("test_projects/cycle/src/lib.rs"),
+        &Options::default(),
+    )
+    .unwrap_err()
+    .into_errors();
+    let mut locations: Vec<_> = errors
+        .iter()
+        .map(|(_, error)| error.location().unwrap().to_string())
+        .collect();
+    locations.sort();
+    assert_eq!(
+        locations,
+        [
+            "test_projects/cycle/src/lib.rs:2:1",
+            "test_projects/cycle/src/snippet.rs:1:1"
+        ]
+    );
+
+    let srcfiles = srcfiles::crate_srcfiles(
+        PathBuf::from("test_projects/nested_include/src/lib.rs"),
+        &Options::default(),
+    )
+    .unwrap();
+    let location = |path: &str| {
+        let desc = srcfiles.iter().find(|x| x.path.ends_with(path)).unwrap();
+        desc.parents[0].location.to_string()
+    };
+    assert_eq!(
+        location("data.txt"),
+        "test_projects/nested_include/snippets/a.rs:3:20"
+    );
+    assert_eq!(
+        location("m.rs"),
+        "test_projects/nested_include/snippets/a.rs:5:1"
+    );
+}
+
+#[test]
+fn diagnostic_test() {
+    let result = srcfiles::crate_srcfiles(
+        PathBuf::from("test_projects/paths/src/main.rs"),
+        &Options::default(),
+    )
+    .unwrap_err();
+    let diagnostics = Diagnostic::from_errors(&result);
+    assert_eq!(diagnostics.len(), 2);
+
+    let diagnostic = &diagnostics[0];
+    assert_eq!(diagnostic.severity, Severity::Error);
+    assert_eq!(diagnostic.message, "unresolved module `c`");
+    assert_eq!(
+        diagnostic.notes,
+        ["tried `test_projects/paths/src/b/c.rs` and `test_projects/paths/src/b/c/mod.rs`"]
+    );
+    assert!(diagnostic.help.is_some());
+    assert_eq!(
+        diagnostic.to_string().lines().take(5).collect::<Vec<_>>(),
+        [
+            "error[E0583]: unresolved module `c`",
+            " --> test_projects/paths/src/b.rs:1:1",
+            "  |",
+            "1 | mod c;",
+            "  | ^^^^^^",
+        ]
+    );
+
+    // Source lines are read before paths are styled, here relative to
+    // test_projects/paths
+    let options = Options {
+        path_style: PathStyle::RelativeToCrateRoot,
+        ..Options::default()
+    };
+    let result =
+        srcfiles::crate_srcfiles(PathBuf::from("test_projects/paths/src/main.rs"), &options)
+            .unwrap_err();
+    let diagnostics = Diagnostic::from_errors(&result);
+    assert_eq!(
+        diagnostics[0]
+            .to_string()
+            .lines()
+            .take(5)
+            .collect::<Vec<_>>(),
+        [
+            "error[E0583]: unresolved module `c`",
+            " --> src/b.rs:1:1",
+            "  |",
+            "1 | mod c;",
+            "  | ^^^^^^",
+        ]
+    );
+
+    // Only the invocation is underlined, not the rest of the line
+    let srcfiles = srcfiles::crate_srcfiles(
+        PathBuf::from("test_projects/dedup/src/lib.rs"),
+        &Options::default(),
+    )
+    .unwrap();
+    let data = srcfiles
+        .iter()
+        .find(|x| x.path.ends_with("data.txt"))
+        .unwrap();
+    let inclusion = data
+        .parents
+        .iter()
+        .find(|x| x.parent_file.ends_with("lib.rs"))
+        .unwrap();
+    let diagnostic = Diagnostic {
+        severity: Severity::Error,
+        code: None,
+        message: "include".to_owned(),
+        location: Some(inclusion.location.clone()),
+        source_line: Some(r#"const A: &str = include_str!("data.txt");"#.to_owned()),
+        notes: vec![],
+        help: None,
+    };
+    assert_eq!(
+        diagnostic.to_string().lines().skip(3).collect::<Vec<_>>(),
+        [
+            "6 | const A: &str = include_str!(\"data.txt\");",
+            "  |                 ^^^^^^^^^^^^^^^^^^^^^^^^",
+        ]
+    );
+}
+
+#[test]
+fn ambiguous_module_test() {
+    let result = srcfiles::crate_srcfiles(
+        PathBuf::from("test_projects/ambiguous/src/lib.rs"),
+        &Options::default(),
+    )
+    .unwrap_err();
+    let diagnostics = Diagnostic::from_errors(&result);
+    let (srcfiles, errors) = (result.get_sources(), result.into_errors());
+
+    assert_eq!(srcfiles.len(), 1);
+
+    assert_eq!(errors.len(), 3);
+    match &errors[0].1 {
+        Error::AmbiguousModule(name, paths, location) => {
+            assert_eq!(name, "foo");
+            assert_eq!(
+                paths,
+                &[
+                    PathBuf::from("test_projects/ambiguous/src/foo.rs"),
+                    PathBuf::from("test_projects/ambiguous/src/foo/mod.rs"),
+                ]
+            );
+            assert_eq!(location.line, 1);
+        }
+        error => panic!("Unexpected error {}", error),
+    }
+    assert_eq!(diagnostics[0].code, Some("E0761"));
+
+    // Like for rustc, `src/bar/../foo.rs` needs the directory `src/bar`, which does not exist
+    match &errors[1].1 {
+        Error::MissingFile(desc) => {
+            assert_eq!(desc.module_path, ["crate", "bar", "foo"]);
+            assert_eq!(desc.parents[0].location.line, 5);
+        }
+        error => panic!("Unexpected error {}", error),
+    }
+
+    // Named without `r#`, like rustc does
+    match &errors[2].1 {
+        Error::AmbiguousModule(name, _, _) => assert_eq!(name, "type"),
+        error => panic!("Unexpected error {}", error),
+    }
+    assert!(diagnostics[2]
+        .message
+        .starts_with("file for module `type` found at both"));
+}
+
+#[test]
+fn keyword_modules_test() {
+    let result = srcfiles::crate_srcfiles(
+        PathBuf::from("test_projects/keywords/src/lib.rs"),
+        &Options::default(),
+    )
+    .unwrap_err();
+    let (srcfiles, errors) = (result.get_sources(), result.into_errors());
+
+    assert_eq!(srcfiles.len(), 5);
+    assert_has_source(&srcfiles, "test_projects/keywords/src/type.rs");
+    assert_has_source(&srcfiles, "test_projects/keywords/src/type/async.rs");
+    assert_has_source(&srcfiles, "test_projects/keywords/src/match/struct.rs");
+    assert_has_source(&srcfiles, "test_projects/keywords/src/cafe.rs");
+
+    let module_path = |path: &str| {
+        srcfiles
+            .iter()
+            .find(|x| x.path.ends_with(path))
+            .unwrap()
+            .module_path
+            .join("::")
+    };
+    assert_eq!(module_path("async.rs"), "crate::type::async");
+    assert_eq!(module_path("struct.rs"), "crate::match::struct");
+
+    // naïve.rs exists, but rustc does not look for it
+    assert_eq!(errors.len(), 1);
+    match &errors[0].1 {
+        Error::NonAsciiModuleName(name, location) => {
+            assert_eq!(name, "naïve");
+            assert_eq!(location.line, 10);
+        }
+        error => panic!("Unexpected error {}", error),
+    }
+}
+
+#[test]
+fn block_modules_test() {
+    let result = srcfiles::crate_srcfiles(
+        PathBuf::from("test_projects/blocks/src/lib.rs"),
+        &Options::default(),
+    )
+    .unwrap_err();
+    let (srcfiles, errors) = (result.get_sources(), result.into_errors());
+
+    // #[path] in a block is relative to the directory of the file, even for sub.rs
+    assert_eq!(srcfiles.len(), 6);
+    assert_has_source(&srcfiles, "test_projects/blocks/src/x.rs");
+    assert_has_source(&srcfiles, "test_projects/blocks/src/z.rs");
+    assert_has_source(&srcfiles, "test_projects/blocks/src/inl/y.rs");
+    assert_has_source(&srcfiles, "test_projects/blocks/src/sub/inl2/y.rs");
+
+    let errors: Vec<_> = errors
+        .iter()
+        .map(|(_, error)| match error {
+            Error::ModuleInBlock(name, location) => (name.as_str(), location.line),
+            error => panic!("Unexpected error {}", error),
+        })
+        .collect();
+    assert_eq!(errors, [("q", 7), ("e", 12)]);
+}
+
+/// Files rustc reads for test_projects/resolution
+const RESOLUTION_FILES: &[&str] = &[
+    "snippets/from_top.rs",
+    "snippets/i/plain.rs",
+    "snippets/items.rs",
+    "snippets/p.rs",
+    "snippets/plain.rs",
+    "snippets/top.rs",
+    "src/adjacent.rs",
+    "src/adjacent/i1/i2/p2.rs",
+    "src/adjacent/i1/i2/plain.rs",
+    "src/adjacent/i1/ip2/p.rs",
+    "src/adjacent/i1/ip2/plain.rs",
+    "src/adjacent/i1/p1.rs",
+    "src/adjacent/i1/plain.rs",
+    "src/adjacent/plain.rs",
+    "src/adjacent/plain/inner/leaf.rs",
+    "src/adjacent/plain/leaf.rs",
+    "src/in_block.rs",
+    "src/inline_in_block/p.rs",
+    "src/inner/leaf.rs",
+    "src/ip1/i2/p.rs",
+    "src/ip1/i2/plain.rs",
+    "src/ip1/p.rs",
+    "src/ip1/plain.rs",
+    "src/ip_in_block/plain.rs",
+    "src/leaf.rs",
+    "src/lib.rs",
+    "src/mod_rs/i1/i2/p2.rs",
+    "src/mod_rs/i1/i2/plain.rs",
+    "src/mod_rs/i1/ip2/p.rs",
+    "src/mod_rs/i1/ip2/plain.rs",
+    "src/mod_rs/i1/p1.rs",
+    "src/mod_rs/i1/plain.rs",
+    "src/mod_rs/in_block.rs",
+    "src/mod_rs/inline_in_block/p.rs",
+    "src/mod_rs/ip1/i2/p.rs",
+    "src/mod_rs/ip1/i2/plain.rs",
+    "src/mod_rs/ip1/p.rs",
+    "src/mod_rs/ip1/plain.rs",
+    "src/mod_rs/ip_in_block/plain.rs",
+    "src/mod_rs/mod.rs",
+    "src/mod_rs/p0.rs",
+    "src/mod_rs/plain.rs",
+    "src/mod_rs/plain/inner/leaf.rs",
+    "src/mod_rs/plain/leaf.rs",
+    "src/other/i1/i2/p2.rs",
+    "src/other/i1/i2/plain.rs",
+    "src/other/i1/ip2/p.rs",
+    "src/other/i1/ip2/plain.rs",
+    "src/other/i1/p1.rs",
+    "src/other/i1/plain.rs",
+    "src/other/in_block.rs",
+    "src/other/inline_in_block/p.rs",
+    "src/other/ip1/i2/p.rs",
+    "src/other/ip1/i2/plain.rs",
+    "src/other/ip1/p.rs",
+    "src/other/ip1/plain.rs",
+    "src/other/ip_in_block/plain.rs",
+    "src/other/p0.rs",
+    "src/other/path_file.rs",
+    "src/other/plain.rs",
+    "src/p0.rs",
+];
+
+#[test]
+fn resolution_test() {
+    let srcfiles = srcfiles::crate_srcfiles(
+        PathBuf::from("test_projects/resolution/src/lib.rs"),
+        &Options {
+            path_style: PathStyle::RelativeToCrateRoot,
+            ..Options::default()
+        },
+    )
+    .unwrap();
+
+    let mut paths: Vec<_> = srcfiles.iter().map(|x| x.path.to_str().unwrap()).collect();
+    paths.sort_unstable();
+    assert_eq!(paths, RESOLUTION_FILES);
+}
+
+/// Cfg options rustc enables for the host, by `rustc --print cfg`
+fn rustc_cfg_set() -> CfgSet {
+    let output = Command::new("rustc")
+        .args(["--print", "cfg"])
+        .output()
+        .unwrap();
+    let mut cfg_set = CfgSet::new();
+
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        cfg_set.insert_spec(line);
+    }
+
+    cfg_set
+}
+
+/// Fixture roots rustc compiles. The others have errors on purpose or use
+/// external crates
+const RUSTC_FIXTURES: &[&str] = &[
+    "test_projects/attrs/src/lib.rs",
+    "test_projects/cfg/src/lib.rs",
+    "test_projects/cfg_attr/src/lib.rs",
+    "test_projects/dedup/src/lib.rs",
+    "test_projects/env/src/lib.rs",
+    "test_projects/inline/src/lib.rs",
+    "test_projects/nested_include/src/lib.rs",
+    "test_projects/resolution/src/lib.rs",
+    "test_projects/snippets/src/lib.rs",
+];
+
+/// Compares `crate_srcfiles` with the dep-info of the local rustc for every
+/// fixture in `RUSTC_FIXTURES`, and checks that rustc rejects the others
+#[test]
+fn rustc_dep_info_test() {
+    if !rustc_available() {
+        eprintln!("rustc not found, skipping");
+        return;
+    }
+
+    let cfg_set = rustc_cfg_set();
+
+    let mut fixtures: Vec<_> = std::fs::read_dir("test_projects")
+        .unwrap()
+        .map(|x| x.unwrap().path())
+        .collect();
+    fixtures.sort();
+
+    let mut compared = vec![];
+    let mut divergences = vec![];
+
+    for fixture in fixtures {
+        let manifest_dir = std::env::current_dir().unwrap().join(&fixture);
+        let mut env = vec![(
+            "CARGO_MANIFEST_DIR".to_owned(),
+            manifest_dir.to_string_lossy().into_owned(),
+        )];
+
+        if fixture.join("out").is_dir() {
+            env.push((
+                "OUT_DIR".to_owned(),
+                manifest_dir.join("out").to_string_lossy().into_owned(),
+            ));
+        }
+
+        for root in ["src/lib.rs", "src/main.rs"] {
+            let root = fixture.join(root);
+            if !root.is_file() {
+                continue;
+            }
+
+            let expected = match rustc_srcfiles(&root, &env) {
+                Some(expected) => expected,
+                None => continue,
+            };
+
+            let options = Options {
+                cfg_set: Some(cfg_set.clone()),
+                env: env.iter().cloned().collect(),
+                ..Options::default()
+            };
+            let found: Vec<_> = match srcfiles::crate_srcfiles(root.clone(), &options) {
+                Ok(srcfiles) => srcfiles.into_iter().map(|x| x.path).collect(),
+                Err(result) => result.into_sources().into_iter().map(|x| x.path).collect(),
+            };
+
+            let missing: Vec<_> = expected.iter().filter(|x| !found.contains(x)).collect();
+            let extra: Vec<_> = found.iter().filter(|x| !expected.contains(x)).collect();
+
+            if !missing.is_empty() || !extra.is_empty() {
+                divergences.push(format!(
+                    "{}: missing {:?}, extra {:?}",
+                    root.display(),
+                    missing,
+                    extra
+                ));
+            }
+
+            compared.push(root.to_string_lossy().into_owned());
+        }
+    }
+
+    assert!(divergences.is_empty(), "{}", divergences.join("\n"));
+    assert_eq!(compared, RUSTC_FIXTURES);
+}
+
+#[test]
+fn malformed_input_test() {
+    let options = Options::default();
+
+    for root in ["", "/"] {
+        let source = SourceFileDesc::new(
+            PathBuf::from(root),
+            SourceFileType::RustSource(ModType::ModRs),
+        );
+        let content = "mod a;\nmod b { mod c; }\ninclude!(\"d.rs\");\n::foo!();\n";
+
+        let (sources, errors) = srcfiles::process_source_text(&source, content, &options).unwrap();
+        assert!(sources.is_empty());
+        assert_eq!(errors.len(), 3, "{:?}", errors);
+        assert!(errors.iter().all(|x| matches!(x, Error::InvalidPath(..))));
+    }
+
+    let source = SourceFileDesc::new(
+        PathBuf::from("src/lib.rs"),
+        SourceFileType::RustSource(ModType::ModRs),
+    );
+
+    for content in [
+        "mod",
+        "#[cfg(not())] mod a;",
+        "cfg_if::cfg_if! { if #[cfg(a)] #[cfg(b)] {} }",
+    ] {
+        let _ = srcfiles::process_source_text(&source, content, &options);
+    }
+}
+
+#[test]
+fn caller_spans_test() {
+    let tokens: proc_macro2::TokenStream = "\n  fn a() {}".parse().unwrap();
+    let span = tokens.into_iter().next().unwrap().span();
+
+    let _ = srcfiles::crate_srcfiles(
+        PathBuf::from("test_projects/simple/src/main.rs"),
+        &Options::default(),
+    );
+    let source = SourceFileDesc::new(
+        PathBuf::from("src/lib.rs"),
+        SourceFileType::RustSource(ModType::ModRs),
+    );
+    let _ = srcfiles::process_source_text(&source, "mod a;", &Options::default());
+
+    // Parsing happens on another thread, spans of the caller stay valid
+    assert_eq!(span.start().line, 2);
+    assert_eq!(span.start().column, 2);
+}